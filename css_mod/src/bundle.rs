@@ -0,0 +1,36 @@
+use std::sync::OnceLock;
+
+// CSS bundle embedded into binary of application, which is registered by css_mod::init!(), since
+// it is generated into output directory of application rather than this crate
+static BUNDLE_CSS: OnceLock<&'static str> = OnceLock::new();
+
+/// Registers CSS bundle embedded into binary, so it can be retrieved with [`bundle_css`].
+#[doc(hidden)]
+pub fn set_bundle_css(css: &'static str) {
+    BUNDLE_CSS.get_or_init(|| css);
+}
+
+/// Gets CSS bundle generated by [`css_mod::Compiler`](crate::Compiler) as a string embedded into
+/// binary.
+///
+/// Useful when styles should be applied as a string (eg. inside shadow root or webview). Bundle is
+/// registered by [`css_mod::init!`](crate::init), so it should be called first. Bundle is empty if
+/// mappings are built separately and used with
+/// [`Compiler::use_mappings`](crate::Compiler::use_mappings).
+///
+/// # Panics
+///
+/// Panics if `css_mod::init!()` was not called before.
+///
+/// # Example
+///
+/// ```ignore
+/// css_mod::init!();
+///
+/// let css: &'static str = css_mod::bundle_css();
+/// ```
+pub fn bundle_css() -> &'static str {
+    BUNDLE_CSS
+        .get()
+        .expect("CSS bundle was not registered. Help: call css_mod::init!() first")
+}
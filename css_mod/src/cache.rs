@@ -0,0 +1,212 @@
+use crate::parsing::ast::{Module, Stylesheet};
+use crate::utils::{hash, write_file};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// On-disk cache of transformed CSS modules, which allows to skip parsing and transforming of
+/// modules which were not changed since previous compilation.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cache {
+    // hash of compiler options affecting transformation, cache is discarded when options change
+    fingerprint: u64,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+/// Transformed module along with hashes of files it was transformed from.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    content_hash: u64,
+    // hashes of contents of files transformed module depends on, ie. modules it imports
+    // (directly or transitively) and assets referenced by them
+    dependencies: BTreeMap<PathBuf, u64>,
+    module: Module,
+}
+
+impl Cache {
+    /// Reads cache from file, or creates empty cache if file is absent, corrupted or was written
+    /// with other compiler options.
+    pub fn read(file_path: &Path, fingerprint: u64) -> Self {
+        let cache = fs::read(file_path)
+            .ok()
+            .and_then(|content| serde_json::from_slice::<Cache>(&content).ok());
+
+        match cache {
+            Some(cache) if cache.fingerprint == fingerprint => cache,
+            _ => Cache {
+                fingerprint,
+                entries: BTreeMap::new(),
+            },
+        }
+    }
+
+    pub fn write(&self, file_path: &Path) -> Result<()> {
+        write_file(file_path, serde_json::to_string(self)?)
+    }
+
+    /// Creates cache from modules of compiled stylesheet.
+    pub fn new(stylesheet: &Stylesheet, fingerprint: u64) -> Self {
+        // use hashes of contents modules were transformed from, rather than current contents of
+        // module files, which could be changed during compilation
+        let content_hash = |path: &Path| match stylesheet.content_hashes.get(path) {
+            Some(hash) => Some(*hash),
+            None => file_hash(path),
+        };
+
+        let entries = stylesheet
+            .modules
+            .iter()
+            .filter_map(|(module_path, module)| {
+                let mut dependency_paths = BTreeSet::new();
+                collect_dependencies(stylesheet, module, &mut dependency_paths);
+
+                let dependencies = dependency_paths
+                    .into_iter()
+                    .map(|path| content_hash(&path).map(|hash| (path, hash)))
+                    .collect::<Option<_>>()?;
+
+                Some((
+                    module_path.clone(),
+                    Entry {
+                        content_hash: content_hash(module_path)?,
+                        dependencies,
+                        module: module.clone(),
+                    },
+                ))
+            })
+            .collect();
+
+        Cache {
+            fingerprint,
+            entries,
+        }
+    }
+
+    /// Takes transformed module from cache, unless module or files it depends on were changed.
+    pub fn take(&mut self, module_path: &Path, content: &str) -> Option<Module> {
+        self.remove(module_path)?.into_module(content)
+    }
+
+    /// Removes entry of module from cache, so it can be checked for changes separately (eg. on
+    /// other thread).
+    pub fn remove(&mut self, module_path: &Path) -> Option<Entry> {
+        self.entries.remove(module_path)
+    }
+}
+
+impl Entry {
+    /// Gets transformed module, unless module or files it depends on were changed.
+    pub fn into_module(self, content: &str) -> Option<Module> {
+        let is_changed = self.content_hash != hash(content.as_bytes())
+            || self
+                .dependencies
+                .iter()
+                .any(|(path, hash)| file_hash(path) != Some(*hash));
+
+        if is_changed {
+            return None;
+        }
+
+        Some(self.module)
+    }
+}
+
+/// Collects files which transformed module depends on: assets it references and modules it
+/// imports, since names and content of imported modules are merged into importing module.
+fn collect_dependencies(stylesheet: &Stylesheet, module: &Module, paths: &mut BTreeSet<PathBuf>) {
+    paths.extend(module.assets.keys().cloned());
+
+    for import_path in module.imports.iter().chain(&module.conditional_imports) {
+        if paths.insert(import_path.clone()) {
+            if let Some(import) = stylesheet.modules.get(import_path) {
+                collect_dependencies(stylesheet, import, paths);
+            }
+        }
+    }
+}
+
+fn file_hash(file_path: &Path) -> Option<u64> {
+    fs::read(file_path).ok().map(|content| hash(&content))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::utils::write_test_files;
+
+    fn compile(dir: &Path, cache: Option<Cache>) -> Stylesheet {
+        let mut stylesheet = Stylesheet {
+            cache,
+            ..Stylesheet::default()
+        };
+        stylesheet.add_module(&dir.join("a.css")).unwrap();
+        stylesheet
+    }
+
+    #[test]
+    fn reuses_unchanged_modules() {
+        let dir = write_test_files(
+            "reuses_unchanged_modules",
+            &[
+                ("a.css", "@import './b.css' screen; .a { color: red; }"),
+                ("b.css", "@import './c.css'; .b { color: green; }"),
+                ("c.css", ".c { background: url(c.png); }"),
+                ("c.png", "c"),
+            ],
+        );
+
+        let stylesheet = compile(&dir, None);
+        let cache = Cache::new(&stylesheet, 1);
+
+        // all modules depend on asset
+        assert_eq!(cache.entries.len(), 3);
+        assert!(cache
+            .entries
+            .values()
+            .all(|entry| entry.dependencies.contains_key(&dir.join("c.png"))));
+
+        // output is the same when modules are taken from cache
+        let cached_stylesheet = compile(&dir, Some(cache));
+        assert_eq!(cached_stylesheet.to_string(), stylesheet.to_string());
+        assert_eq!(cached_stylesheet.modules, stylesheet.modules);
+        assert_eq!(cached_stylesheet.assets, stylesheet.assets);
+        assert!(cached_stylesheet.cache.unwrap().entries.is_empty());
+
+        // importing modules are transformed again when imported module is changed
+        let mut cache = Cache::new(&stylesheet, 1);
+        write_file(&dir.join("c.css"), ".c { color: blue; }".into()).unwrap();
+        assert!(cache
+            .take(&dir.join("a.css"), &read(&dir, "a.css"))
+            .is_none());
+        assert!(cache
+            .take(&dir.join("b.css"), &read(&dir, "b.css"))
+            .is_none());
+        assert!(cache
+            .take(&dir.join("c.css"), &read(&dir, "c.css"))
+            .is_none());
+    }
+
+    #[test]
+    fn discards_cache_with_other_fingerprint() {
+        let dir = write_test_files(
+            "discards_cache_with_other_fingerprint",
+            &[("a.css", ".a { color: red; }")],
+        );
+        let cache_path = dir.join("cache.json");
+
+        Cache::new(&compile(&dir, None), 1)
+            .write(&cache_path)
+            .unwrap();
+
+        assert_eq!(Cache::read(&cache_path, 1).entries.len(), 1);
+        assert!(Cache::read(&cache_path, 2).entries.is_empty());
+        assert!(Cache::read(&dir.join("absent.json"), 1).entries.is_empty());
+    }
+
+    fn read(dir: &Path, file_name: &str) -> String {
+        fs::read_to_string(dir.join(file_name)).unwrap()
+    }
+}
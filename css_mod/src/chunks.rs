@@ -0,0 +1,37 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Loads CSS chunks of module on first call from particular call site.
+///
+/// Chunks are only loaded with `dom` feature enabled, otherwise they are expected to be loaded
+/// by other means.
+///
+/// Arguments:
+///
+/// * `loaded`: Flag of particular call site, which allows to skip loading on subsequent calls
+///   without locking.
+/// * `urls`: URLs of chunks of module and modules it depends on, in order of loading.
+pub fn load_chunks(loaded: &AtomicBool, urls: &[&'static str]) {
+    if loaded.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    #[cfg(feature = "dom")]
+    {
+        use std::collections::BTreeSet;
+        use std::sync::{Mutex, PoisonError};
+
+        // urls of chunks which were loaded already from any call site
+        static LOADED_URLS: Mutex<BTreeSet<&str>> = Mutex::new(BTreeSet::new());
+
+        let mut loaded_urls = LOADED_URLS.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for url in urls {
+            if loaded_urls.insert(url) {
+                crate::dom::inject_link(url);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "dom"))]
+    let _ = urls;
+}
@@ -1,11 +1,36 @@
-use crate::parsing::ast;
-use crate::utils::{get_workspace_dir, write_file};
+use crate::cache::Cache;
+use crate::naming::Naming;
+use crate::parsing::ast::{self, Mode};
+use crate::utils::{get_workspace_dir, glob_base_dir, hash, normalize_path, write_file};
+use crate::watcher::Watcher;
 use anyhow::{Context, Result};
-use glob::glob;
-use quote::quote;
-use std::collections::BTreeSet;
+use glob::{glob, Pattern};
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
+use std::fs::{self, copy};
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+// name of the file with exports of all CSS modules, should be in sync with css_mod_macros crate
+const EXPORTS_FILE_NAME: &str = "css_mod_exports.json";
+
+// name of the file with urls of CSS chunks of all modules, should be in sync with css_mod_macros
+// crate
+const CHUNKS_FILE_NAME: &str = "css_mod_chunks.json";
+
+// name of the file with paths of CSS files embedded into binary, should be in sync with
+// css_mod_macros crate
+const TEXTS_FILE_NAME: &str = "css_mod_texts.json";
+
+// name of the directory with CSS files embedded into binary
+const TEXTS_DIR_NAME: &str = "css_mod_texts";
+
+// name of the file with modules transformed by previous compilation
+const CACHE_FILE_NAME: &str = "css_mod_cache.json";
+
+// interval of checking files for changes in watch mode
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
 
 #[allow(clippy::needless_doctest_main)]
 /// CSS Modules compiler.
@@ -25,9 +50,18 @@ use std::path::{Path, PathBuf};
 /// ```
 #[derive(Debug, Default)]
 pub struct Compiler {
-    // use sorted set instead of hash set so output bundle is rendered deterministically with the
-    // same content across recompilations
-    input_modules: BTreeSet<PathBuf>,
+    // input modules and glob patterns in order they were added, which are resolved on each
+    // compilation so modules added to directories searched with globs are picked up in watch mode
+    inputs: Vec<(Input, Option<Mode>)>,
+    // directories which input modules are searched in with glob patterns
+    glob_base_dirs: BTreeSet<PathBuf>,
+    default_mode: Mode,
+    naming: Option<Naming>,
+    naming_salt: String,
+    inline_assets_limit: Option<u64>,
+    chunks_public_path: Option<String>,
+    embed_css: bool,
+    base_dir: Option<PathBuf>,
 }
 
 impl Compiler {
@@ -35,13 +69,128 @@ impl Compiler {
         Compiler::default()
     }
 
+    /// Sets default scoping mode for names in CSS modules.
+    ///
+    /// Names are scoped locally by default. Mode can be overridden for particular modules with
+    /// [`add_module_with_mode`](Self::add_module_with_mode), and for particular selectors with
+    /// `:local` / `:global` pseudo-classes.
+    pub fn default_mode(&mut self, mode: Mode) -> &mut Self {
+        self.default_mode = mode;
+        self
+    }
+
+    /// Sets strategy of generating global names for local names of CSS modules.
+    ///
+    /// Accepts template (eg. `"[name]_[local]_[hash:6]"`) or custom function created with
+    /// [`Naming::custom`]. Default template is `"[name]__[local]__[hash:8]"`, or
+    /// [`Naming::minified`] in release builds.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use css_mod::{Compiler, Naming};
+    ///
+    /// Compiler::new()
+    ///     .naming("[name]_[local]_[hash:6]").unwrap()
+    ///     .naming(Naming::custom(|_path, local, hash| format!("{}-{:x}", local, hash))).unwrap();
+    /// ```
+    pub fn naming(&mut self, naming: impl Into<Naming>) -> Result<&mut Self> {
+        let naming = naming.into();
+        naming.validate()?;
+
+        self.naming = Some(naming);
+        Ok(self)
+    }
+
+    /// Sets salt mixed into hashes of global names (`[hash]` placeholder of naming template).
+    ///
+    /// Global names are stable across recompilations, since their hashes only depend on module
+    /// path relative to workspace directory and local name. Salt allows to change them
+    /// intentionally (eg. to avoid clashes with names generated by other builds on the same page).
+    pub fn naming_salt(&mut self, salt: &str) -> &mut Self {
+        self.naming_salt = salt.into();
+        self
+    }
+
+    /// Enables inlining of asset files referenced with `url()` into CSS bundle as data URIs.
+    ///
+    /// By default asset files are copied next to CSS bundle under content-hashed names.
+    ///
+    /// Arguments:
+    ///
+    /// * `limit`: Max size of asset file in bytes to inline it instead of copying.
+    pub fn inline_assets(&mut self, limit: u64) -> &mut Self {
+        self.inline_assets_limit = Some(limit);
+        self
+    }
+
+    /// Enables splitting of CSS into chunks, one per CSS module, which are loaded at runtime on
+    /// first request of CSS module mapping, so styles of modules which are never used are never
+    /// downloaded.
+    ///
+    /// Chunks are written next to CSS bundle under content-hashed names, along with manifest
+    /// `<bundle name>.chunks.json` listing chunks of each module. Chunk of CSS module is loaded
+    /// along with chunks of modules it imports or composes from. Chunks are only loaded with
+    /// `dom` feature enabled, and CSS bundle is still written in case they are served otherwise.
+    /// Since all styles are loaded with chunks, [`css_mod::init!`](crate::init) doesn't inject
+    /// CSS bundle into the document.
+    ///
+    /// Arguments:
+    ///
+    /// * `public_path`: URL path which chunks are served from (eg. `"/assets/"`).
+    pub fn split_chunks(&mut self, public_path: &str) -> &mut Self {
+        let mut public_path = public_path.to_owned();
+        if !public_path.ends_with('/') {
+            public_path.push('/');
+        }
+
+        self.chunks_public_path = Some(public_path);
+        self
+    }
+
+    /// Enables embedding of CSS of each module into binary, which can be retrieved with
+    /// [`Mapping::css_text`](crate::Mapping::css_text) (eg. to apply styles inside shadow root).
+    /// Disabled by default, since CSS of module is embedded for every call to `css_mod::get!()`.
+    ///
+    /// Whole CSS bundle is always available with [`css_mod::bundle_css`](fn@crate::bundle_css).
+    pub fn embed_css(&mut self) -> &mut Self {
+        self.embed_css = true;
+        self
+    }
+
+    /// Sets directory which relative paths of input and output files are resolved against, and
+    /// which module paths in name mappings and hashes of global names are relative to.
+    ///
+    /// In build script defaults to package root directory for relative paths, and workspace
+    /// root directory for module paths. Otherwise defaults to current directory. Should be set
+    /// before adding CSS modules.
+    ///
+    /// Arguments:
+    ///
+    /// * `dir`: Directory path, which may be absolute or relative to current directory.
+    pub fn base_dir(&mut self, dir: &str) -> Result<&mut Self> {
+        self.base_dir = Some(normalize_path(&env::current_dir()?.join(dir)));
+        Ok(self)
+    }
+
     /// Adds CSS module to compile.
     ///
     /// Arguments:
     ///
     /// * `path`: File path, which may be absolute or relative to package root directory.
     pub fn add_module(&mut self, path: &str) -> Result<&mut Self> {
-        self.add_module_buf(PathBuf::from(path))?;
+        self.add_module_buf(PathBuf::from(path), None)?;
+        Ok(self)
+    }
+
+    /// Adds CSS module to compile with specific scoping mode.
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: File path, which may be absolute or relative to package root directory.
+    /// * `mode`: Scoping mode for names in the module.
+    pub fn add_module_with_mode(&mut self, path: &str, mode: Mode) -> Result<&mut Self> {
+        self.add_module_buf(PathBuf::from(path), Some(mode))?;
         Ok(self)
     }
 
@@ -51,76 +200,430 @@ impl Compiler {
     ///
     /// * `pattern`: Glob pattern, which may be absolute or relative to package root directory.
     pub fn add_modules(&mut self, pattern: &str) -> Result<&mut Self> {
-        for entry in glob(pattern).context("Failed to read glob pattern")? {
-            self.add_module_buf(entry?)?;
-        }
+        self.add_modules_glob(pattern, None)?;
         Ok(self)
     }
 
-    fn add_module_buf(&mut self, mut path: PathBuf) -> Result<()> {
-        if path.is_relative() {
-            let manifest_dir = env::var("CARGO_MANIFEST_DIR").context("CARGO_MANIFEST_DIR")?;
-            path = PathBuf::from(manifest_dir).join(path);
-        }
+    /// Adds CSS modules to compile with specific scoping mode.
+    ///
+    /// Arguments:
+    ///
+    /// * `pattern`: Glob pattern, which may be absolute or relative to package root directory.
+    /// * `mode`: Scoping mode for names in the modules.
+    pub fn add_modules_with_mode(&mut self, pattern: &str, mode: Mode) -> Result<&mut Self> {
+        self.add_modules_glob(pattern, Some(mode))?;
+        Ok(self)
+    }
 
-        log::debug!("add css module: {:?}", path);
-        self.input_modules.insert(path);
+    fn add_modules_glob(&mut self, pattern: &str, mode: Option<Mode>) -> Result<()> {
+        let package_dir = self.package_dir()?;
+
+        // watch base directory so modules added later are compiled too
+        let base_dir = package_dir.join(glob_base_dir(pattern));
+        self.glob_base_dirs.insert(normalize_path(&base_dir));
+
+        // resolve relative pattern against package directory rather than current directory
+        let pattern = match Path::new(pattern).is_relative() {
+            true => format!(
+                "{}/{}",
+                Pattern::escape(&package_dir.to_string_lossy()),
+                pattern
+            ),
+            false => pattern.to_owned(),
+        };
+
+        Pattern::new(&pattern).context("Failed to read glob pattern")?;
+        self.inputs.push((Input::Glob(pattern), mode));
 
         Ok(())
     }
 
+    fn add_module_buf(&mut self, path: PathBuf, mode: Option<Mode>) -> Result<()> {
+        let path = self.package_dir()?.join(path);
+        self.inputs.push((Input::Module(path), mode));
+
+        Ok(())
+    }
+
+    /// Gets paths of input CSS modules, along with their scoping modes.
+    pub(crate) fn input_modules(&self) -> Result<BTreeMap<PathBuf, Option<Mode>>> {
+        // use sorted map instead of hash map so output bundle is rendered deterministically with
+        // the same content across recompilations
+        let mut input_modules = BTreeMap::new();
+
+        for (input, mode) in &self.inputs {
+            let paths = match input {
+                Input::Module(path) => vec![path.clone()],
+                Input::Glob(pattern) => glob(pattern)
+                    .context("Failed to read glob pattern")?
+                    .collect::<Result<_, _>>()?,
+            };
+
+            for path in paths {
+                // normalize path so it matches paths of the same module imported from other
+                // modules
+                let path = normalize_path(&path);
+
+                log::debug!("add css module: {:?}", path);
+                input_modules.insert(path, *mode);
+            }
+        }
+
+        Ok(input_modules)
+    }
+
     /// Parses and transforms input CSS modules.
     ///
-    /// Generates CSS bundle file ready to be included on a HTML page, and rust code file with name
-    /// mappings ready to be included into rust project with [`css_mod::init!`](crate::init).
+    /// Generates CSS bundle file ready to be included on a HTML page, and file with name mappings
+    /// of CSS modules ready to be retrieved with [`css_mod::get!`](crate::get) and other macros.
     ///
     /// Arguments:
     ///
     /// * `css_bundle_path`: File path for output CSS bundle, which may be absolute or relative to
-    ///                      package root directory.
+    ///   package root directory.
     pub fn compile(&self, css_bundle_path: &str) -> Result<()> {
-        // parse and transform input CSS files
-        let mut stylesheet = ast::Stylesheet::default();
+        let out_dir = &out_dir()?;
+
+        let input_modules = self.input_modules()?;
+        let mut stylesheet = self.stylesheet(&input_modules)?;
 
-        for module_path in &self.input_modules {
-            stylesheet.add_module(module_path).with_context(|| {
+        // reuse modules transformed by previous compilation, unless global names depend on
+        // order of names across the stylesheet
+        let cache_file_path = out_dir.join(CACHE_FILE_NAME);
+        let cache_fingerprint = match &stylesheet.naming {
+            Naming::Template(template) if !stylesheet.naming.is_order_dependent() => Some(hash(
                 format!(
-                    "Failed to parse and transform CSS module: {:?}",
-                    module_path
+                    "{:?}",
+                    (
+                        template,
+                        &stylesheet.naming_salt,
+                        stylesheet.default_mode,
+                        &stylesheet.module_modes,
+                        &stylesheet.base_dir,
+                        stylesheet.inline_assets_limit,
+                        env!("CARGO_PKG_VERSION"),
+                    )
                 )
-            })?;
+                .as_bytes(),
+            )),
+            _ => None,
+        };
+
+        if let Some(fingerprint) = cache_fingerprint {
+            log::debug!("read cache: {:?}", cache_file_path);
+            stylesheet.cache = Some(Cache::read(&cache_file_path, fingerprint));
         }
 
-        // generate contents for css bundle and mappings code files
-        let mut css_bundle_content = String::new();
-        let mut mappings_code_content = String::new();
+        stylesheet.add_modules(input_modules.keys())?;
 
-        let workspace_dir = get_workspace_dir()?;
-        log::debug!("workspace dir: {:?}", workspace_dir);
+        // rerun build script only when input modules, modules they import or compose from, or
+        // assets are changed, or when modules are added to directories searched with globs
+        for path in self
+            .glob_base_dirs
+            .iter()
+            .chain(stylesheet.modules.keys())
+            .chain(
+                stylesheet
+                    .modules
+                    .values()
+                    .flat_map(|module| module.assets.keys()),
+            )
+        {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
 
-        let is_windows_host = cfg!(windows);
-        log::debug!("is windows host: {}", is_windows_host);
+        let output = self.write_output(&stylesheet, css_bundle_path)?;
+
+        // output exports for css_mod::get!() and other macros, which read them at compile time
+        let exports_file_path = &out_dir.join(EXPORTS_FILE_NAME);
+        log::debug!("output exports: {:?}", exports_file_path);
+        write_file(exports_file_path, serde_json::to_string(&output.exports)?)?;
+
+        // output urls of chunks, which css_mod::get!() and other macros load at runtime
+        let chunk_urls: BTreeMap<&String, Vec<String>> = output
+            .chunks
+            .iter()
+            .map(|(module_file_path, module_chunks)| {
+                let public_path = self.chunks_public_path.as_deref().unwrap_or_default();
+                let urls = module_chunks
+                    .iter()
+                    .map(|file_name| format!("{}{}", public_path, file_name))
+                    .collect();
+
+                (module_file_path, urls)
+            })
+            .collect();
+
+        let chunks_file_path = &out_dir.join(CHUNKS_FILE_NAME);
+        log::debug!("output chunk urls: {:?}", chunks_file_path);
+        write_file(chunks_file_path, serde_json::to_string(&chunk_urls)?)?;
+
+        // output css of modules, which css_mod::get!() embeds into binary
+        let mut css_text_paths = BTreeMap::new();
+
+        for (module_file_path, module_text) in output.texts {
+            // name file after module path, so it's overwritten on recompilation
+            let css_text_path = out_dir
+                .join(TEXTS_DIR_NAME)
+                .join(format!("{:016x}.css", hash(module_file_path.as_bytes())));
+            log::debug!("output css text: {:?}", css_text_path);
+            write_file(&css_text_path, module_text)?;
+
+            let css_text_path = css_text_path
+                .to_str()
+                .context("Failed to construct css text path")?
+                .to_owned();
+            css_text_paths.insert(module_file_path, css_text_path);
+        }
+
+        let texts_file_path = &out_dir.join(TEXTS_FILE_NAME);
+        log::debug!("output css text paths: {:?}", texts_file_path);
+        write_file(texts_file_path, serde_json::to_string(&css_text_paths)?)?;
+
+        // output copy of css bundle, which can be embedded into binary with css_mod::bundle_css!()
+        let embedded_css_bundle_path = &out_dir.join(crate::BUNDLE_FILE_NAME!());
+        log::debug!("output embedded css bundle: {:?}", embedded_css_bundle_path);
+        write_file(embedded_css_bundle_path, output.css_bundle.clone())?;
 
-        mappings_code_content.push_str(
-            // save host os info into mappings since it's impossible to get it outside of build script
-            &quote! {
-                ::css_mod::Mappings::default().is_windows_host(#is_windows_host)
+        // output css which css_mod::init!() injects into the document, unless styles are loaded
+        // with chunks, so they are not loaded twice
+        let injected_css_path = &out_dir.join(crate::INJECTED_FILE_NAME!());
+        log::debug!("output injected css: {:?}", injected_css_path);
+        write_file(
+            injected_css_path,
+            match self.chunks_public_path {
+                Some(_) => String::new(),
+                None => output.css_bundle,
+            },
+        )?;
+
+        if let Some(fingerprint) = cache_fingerprint {
+            log::debug!("output cache: {:?}", cache_file_path);
+            Cache::new(&stylesheet, fingerprint).write(&cache_file_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Parses and transforms input CSS modules outside of build script (eg. from command line
+    /// tool), so neither cargo environment variables are required nor cargo directives are
+    /// printed.
+    ///
+    /// Generates CSS bundle file, and JSON file with name mappings of CSS modules keyed by module
+    /// paths relative to [base directory](Self::base_dir) (eg.
+    /// `{"src/todo.css": {"root": "todo__root__1a2b3c4d"}}`).
+    ///
+    /// Arguments:
+    ///
+    /// * `css_bundle_path`: File path for output CSS bundle, which may be absolute or relative to
+    ///   base directory.
+    /// * `mappings_path`: File path for output name mappings, which may be absolute or relative
+    ///   to base directory.
+    pub fn build(&self, css_bundle_path: &str, mappings_path: &str) -> Result<()> {
+        let input_modules = self.input_modules()?;
+        let mut stylesheet = self.stylesheet(&input_modules)?;
+        stylesheet.add_modules(input_modules.keys())?;
+
+        self.write_build_output(&stylesheet, css_bundle_path, mappings_path)
+    }
+
+    /// Writes output files of [`build`](Self::build).
+    ///
+    /// Mappings file is left untouched if name mappings did not change, so Rust code using them
+    /// is not recompiled (eg. when only declarations of CSS modules were changed).
+    pub(crate) fn write_build_output(
+        &self,
+        stylesheet: &ast::Stylesheet,
+        css_bundle_path: &str,
+        mappings_path: &str,
+    ) -> Result<()> {
+        let output = self.write_output(stylesheet, css_bundle_path)?;
+
+        let mappings_path = self.package_dir()?.join(mappings_path);
+        let mappings = serde_json::to_string_pretty(&output.exports)?;
+
+        if fs::read_to_string(&mappings_path).ok().as_ref() != Some(&mappings) {
+            log::debug!("output mappings: {:?}", mappings_path);
+            write_file(&mappings_path, mappings)?;
+        }
+
+        Ok(())
+    }
+
+    /// Builds output files same as [`build`](Self::build), and rebuilds them whenever input CSS
+    /// modules or files they depend on are changed, or modules are added to directories searched
+    /// with globs. Never returns.
+    ///
+    /// Transformed modules are kept in memory between rebuilds, so only changed modules and
+    /// modules importing or composing from them are parsed and transformed again. Mappings file
+    /// is only rewritten when name mappings change, so Rust code using them with
+    /// [`use_mappings`](Self::use_mappings) is not recompiled when only declarations of CSS
+    /// modules are changed.
+    ///
+    /// Arguments:
+    ///
+    /// * `css_bundle_path`: Same as of [`build`](Self::build).
+    /// * `mappings_path`: Same as of [`build`](Self::build).
+    /// * `on_build`: Callback receiving result of each build. Errors don't stop watching.
+    pub fn watch(
+        &self,
+        css_bundle_path: &str,
+        mappings_path: &str,
+        mut on_build: impl FnMut(Result<()>),
+    ) -> ! {
+        let mut watcher = Watcher::new(self, css_bundle_path, mappings_path);
+
+        loop {
+            if let Some(result) = watcher.poll() {
+                on_build(result);
             }
-            .to_string(),
-        );
 
+            thread::sleep(WATCH_INTERVAL);
+        }
+    }
+
+    /// Makes name mappings written by [`build`](Self::build) or [`watch`](Self::watch) (eg. with
+    /// command line tool) available to [`css_mod::get!`](crate::get) and other macros, instead of
+    /// compiling CSS modules in build script.
+    ///
+    /// Build script is only rerun when mappings file is changed, so Rust code is not recompiled
+    /// when CSS bundle is rebuilt separately. Mappings should be built with workspace root
+    /// directory as base directory. Splitting CSS into chunks and embedding CSS into binary are
+    /// not supported.
+    ///
+    /// Arguments:
+    ///
+    /// * `mappings_path`: File path of name mappings, which may be absolute or relative to
+    ///   package root directory.
+    pub fn use_mappings(&self, mappings_path: &str) -> Result<()> {
+        let out_dir = out_dir()?;
+
+        let mappings_path = self.package_dir()?.join(mappings_path);
+        println!("cargo:rerun-if-changed={}", mappings_path.display());
+
+        let mappings = fs::read_to_string(&mappings_path)
+            .with_context(|| format!("Failed to read name mappings: {:?}", mappings_path))?;
+        // parse mappings so invalid file is reported here rather than by macros
+        let exports: BTreeMap<String, BTreeMap<String, String>> =
+            serde_json::from_str(&mappings)
+                .with_context(|| format!("Failed to parse name mappings: {:?}", mappings_path))?;
+
+        let exports_file_path = &out_dir.join(EXPORTS_FILE_NAME);
+        log::debug!("output exports: {:?}", exports_file_path);
+        write_file(exports_file_path, serde_json::to_string(&exports)?)?;
+
+        // reset chunks and embedded css left by previous compilation, and leave nothing for
+        // css_mod::init!() to inject or css_mod::bundle_css!() to embed, since CSS bundle is
+        // served separately
+        write_file(&out_dir.join(CHUNKS_FILE_NAME), "{}".into())?;
+        write_file(&out_dir.join(TEXTS_FILE_NAME), "{}".into())?;
+        write_file(&out_dir.join(crate::BUNDLE_FILE_NAME!()), String::new())?;
+        write_file(&out_dir.join(crate::INJECTED_FILE_NAME!()), String::new())?;
+
+        Ok(())
+    }
+
+    /// Parses and transforms input CSS modules without writing any output, to check them for
+    /// errors.
+    pub fn check(&self) -> Result<()> {
+        let input_modules = self.input_modules()?;
+        let mut stylesheet = self.stylesheet(&input_modules)?;
+        stylesheet.add_modules(input_modules.keys())?;
+
+        // exports fail on unknown composed names
         for module in stylesheet.modules.values() {
-            for child in &module.children {
-                css_bundle_content.push_str(&format!("{}", child));
-            }
+            stylesheet.exports(module)?;
+        }
 
+        Ok(())
+    }
+
+    /// Gets directory relative input and output paths are resolved against.
+    fn package_dir(&self) -> Result<PathBuf> {
+        match (&self.base_dir, env::var("CARGO_MANIFEST_DIR")) {
+            (Some(base_dir), _) => Ok(base_dir.clone()),
+            (None, Ok(manifest_dir)) => Ok(PathBuf::from(manifest_dir)),
+            (None, Err(_)) => Ok(env::current_dir()?),
+        }
+    }
+
+    /// Gets directory which module paths in name mappings and hashes of global names are
+    /// relative to.
+    fn workspace_dir(&self) -> Result<PathBuf> {
+        match (&self.base_dir, env::var("CARGO_MANIFEST_DIR")) {
+            (Some(base_dir), _) => Ok(base_dir.clone()),
+            (None, Ok(_)) => get_workspace_dir(),
+            (None, Err(_)) => Ok(env::current_dir()?),
+        }
+    }
+
+    /// Creates stylesheet which input CSS modules are parsed and transformed into.
+    pub(crate) fn stylesheet(
+        &self,
+        input_modules: &BTreeMap<PathBuf, Option<Mode>>,
+    ) -> Result<ast::Stylesheet> {
+        let workspace_dir = self.workspace_dir()?;
+        log::debug!("workspace dir: {:?}", workspace_dir);
+
+        Ok(ast::Stylesheet {
+            default_mode: self.default_mode,
+            module_modes: input_modules
+                .iter()
+                .filter_map(|(path, mode)| mode.map(|mode| (path.clone(), mode)))
+                .collect(),
+            naming: match &self.naming {
+                Some(naming) => naming.clone(),
+                // use short names in release builds to reduce size of CSS bundle and binary
+                None if env::var("PROFILE").as_deref() == Ok("release") => Naming::minified(),
+                None => Naming::default(),
+            },
+            naming_salt: self.naming_salt.clone(),
+            base_dir: workspace_dir,
+            inline_assets_limit: self.inline_assets_limit,
+            ..ast::Stylesheet::default()
+        })
+    }
+
+    /// Writes CSS bundle, assets and chunks of transformed stylesheet, and gets contents of
+    /// other output files.
+    fn write_output(&self, stylesheet: &ast::Stylesheet, css_bundle_path: &str) -> Result<Output> {
+        let workspace_dir = &stylesheet.base_dir;
+
+        // generate contents for css bundle, chunks and exports files
+        let css_bundle_content = stylesheet.to_string();
+        let mut modules_exports = BTreeMap::new();
+        let mut modules_chunks = BTreeMap::new();
+        let mut modules_texts = BTreeMap::new();
+
+        let chunks: BTreeMap<&PathBuf, (String, String)> = match self.chunks_public_path {
+            Some(_) => stylesheet
+                .modules
+                .iter()
+                .map(|(module_path, module)| {
+                    let content = module.to_string();
+                    let stem = module_path
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .context("Failed to get module file name")?;
+                    let file_name = format!("{}.{:016x}.css", stem, hash(content.as_bytes()));
+
+                    Ok((module_path, (file_name, content)))
+                })
+                .collect::<Result<_>>()?,
+            None => BTreeMap::new(),
+        };
+
+        let is_windows_host = cfg!(windows);
+        log::debug!("is windows host: {}", is_windows_host);
+
+        for module in stylesheet.modules.values() {
             // css_mod::get!() will look up name mapping with module file path as a key. that path
             // is constructed from file!() macro, which returns path relative to workspace
             // directory. so make sure constructed module path key is relative to workspace
             debug_assert!(module.file_path.is_absolute());
             let mut module_file_path = module
                 .file_path
-                .strip_prefix(&workspace_dir)?
+                .strip_prefix(workspace_dir)?
                 .to_str()
                 .context("Failed to construct relative module path")?
                 .to_owned();
@@ -131,45 +634,132 @@ impl Compiler {
                 module_file_path = module_file_path.replace('\\', "/");
             }
 
-            let mut identifiers = Vec::new();
+            if self.chunks_public_path.is_some() {
+                let module_chunks: Vec<String> = stylesheet
+                    .module_with_dependencies(&module.file_path)
+                    .iter()
+                    .filter_map(|module| chunks.get(&module.file_path))
+                    .map(|(file_name, _)| file_name.clone())
+                    .collect();
 
-            for (old, new) in &module.names {
-                identifiers.push(quote! {(#old, #new)});
+                modules_chunks.insert(module_file_path.clone(), module_chunks);
             }
 
-            let mapping_code = quote! {
-                .add_mapping(
-                    #module_file_path,
-                    [#(#identifiers),*],
-                )
-            };
+            if self.embed_css {
+                let module_text: String = stylesheet
+                    .module_with_dependencies(&module.file_path)
+                    .iter()
+                    .map(|module| module.to_string())
+                    .collect();
 
-            mappings_code_content.push_str(&mapping_code.to_string());
+                modules_texts.insert(module_file_path.clone(), module_text);
+            }
+
+            modules_exports.insert(module_file_path, stylesheet.exports(module)?);
         }
 
         // output css bundle
-        let mut css_bundle_path = PathBuf::from(css_bundle_path);
-        if css_bundle_path.is_relative() {
-            let package_dir = env::var("CARGO_MANIFEST_DIR").context("CARGO_MANIFEST_DIR")?;
-            let package_dir = Path::new(&package_dir);
+        let css_bundle_path = self.package_dir()?.join(css_bundle_path);
+        log::debug!("output css bundle: {:?}", css_bundle_path);
+        write_file(&css_bundle_path, css_bundle_content.clone())?;
 
-            css_bundle_path = package_dir.join(&css_bundle_path);
+        // output assets next to css bundle, since their urls are relative to it
+        let assets_dir = css_bundle_path
+            .parent()
+            .context("Failed to get parent directory")?;
+
+        for (asset_path, asset_file_name) in &stylesheet.assets {
+            let output_path = assets_dir.join(asset_file_name);
+            log::debug!("output asset: {:?} -> {:?}", asset_path, output_path);
+            copy(asset_path, &output_path)
+                .with_context(|| format!("Failed to copy asset file: {:?}", asset_path))?;
         }
 
-        log::debug!("output css bundle: {:?}", css_bundle_path);
-        write_file(&css_bundle_path, css_bundle_content)?;
+        // output chunks next to css bundle, so their asset urls stay valid
+        for (chunk_file_name, chunk_content) in chunks.values() {
+            let output_path = assets_dir.join(chunk_file_name);
+            log::debug!("output css chunk: {:?}", output_path);
+            write_file(&output_path, chunk_content.clone())?;
+        }
 
-        // output mappings code
-        let out_dir = env::var("OUT_DIR").context(
-            "OUT_DIR environment variable was not found. \
-                Help: CSS modules compilation should run from cargo build script.",
-        )?;
-        let out_dir = Path::new(&out_dir);
+        if self.chunks_public_path.is_some() {
+            let manifest_path = css_bundle_path.with_extension("chunks.json");
+            log::debug!("output css chunks manifest: {:?}", manifest_path);
+            write_file(&manifest_path, serde_json::to_string(&modules_chunks)?)?;
+        }
+
+        Ok(Output {
+            css_bundle: css_bundle_content,
+            exports: modules_exports,
+            chunks: modules_chunks,
+            texts: modules_texts,
+        })
+    }
+}
 
-        let mappings_code_file_path = &out_dir.join(crate::MAPPINGS_FILE_NAME!());
-        log::debug!("output mappings code: {:?}", mappings_code_file_path);
-        write_file(mappings_code_file_path, mappings_code_content)?;
+/// Gets directory for files generated by build script.
+fn out_dir() -> Result<PathBuf> {
+    let out_dir = env::var("OUT_DIR").context(
+        "OUT_DIR environment variable was not found. \
+            Help: CSS modules compilation should run from cargo build script.",
+    )?;
 
-        Ok(())
+    Ok(PathBuf::from(out_dir))
+}
+
+/// Input CSS module or glob pattern of input CSS modules, resolved against package directory.
+#[derive(Debug)]
+enum Input {
+    Module(PathBuf),
+    Glob(String),
+}
+
+/// Contents of output files, which are keyed by module paths relative to workspace directory.
+struct Output {
+    css_bundle: String,
+    exports: BTreeMap<String, BTreeMap<String, String>>,
+    // file names of chunks each module is split into
+    chunks: BTreeMap<String, Vec<String>>,
+    // css of each module along with modules it depends on
+    texts: BTreeMap<String, String>,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::utils::write_test_files;
+
+    #[test]
+    fn build_conditional_import_of_input_module() {
+        let dir = write_test_files(
+            "build_conditional_import_of_input_module",
+            &[
+                (
+                    "src/a.css",
+                    "@import \"./b.css\" screen; .a { color: red; }",
+                ),
+                ("src/b.css", ".b { color: green; }"),
+            ],
+        );
+
+        let mut compiler = Compiler::new();
+        compiler
+            .base_dir(dir.to_str().unwrap())
+            .unwrap()
+            .naming("[name]__[local]")
+            .unwrap()
+            .add_modules("src/*.css")
+            .unwrap();
+        compiler.build("out/app.css", "out/mappings.json").unwrap();
+
+        // imported module is only rendered inside of import conditions
+        assert_eq!(
+            fs::read_to_string(dir.join("out/app.css")).unwrap(),
+            "@media screen { .b__b { color: green; }\n}\n.a__a { color: red; }\n"
+        );
+        assert!(fs::read_to_string(dir.join("out/mappings.json"))
+            .unwrap()
+            .contains("src/b.css"));
     }
 }
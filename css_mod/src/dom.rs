@@ -0,0 +1,85 @@
+use web_sys::{window, Document, HtmlHeadElement};
+
+// id of <style> element with injected CSS bundle
+const STYLE_ELEMENT_ID: &str = "css_mod";
+
+/// Gets document and its head element, or none if there is no document (eg. when rendering on
+/// server or running tests natively).
+fn document() -> Option<(Document, HtmlHeadElement)> {
+    // web apis are not available outside of browser, and accessing them panics
+    if !cfg!(target_arch = "wasm32") {
+        return None;
+    }
+
+    let document = match window().and_then(|window| window.document()) {
+        Some(document) => document,
+        None => {
+            log::debug!("no document to inject css into");
+            return None;
+        }
+    };
+
+    match document.head() {
+        Some(head) => Some((document, head)),
+        None => {
+            log::warn!("failed to inject css: document has no head element");
+            None
+        }
+    }
+}
+
+/// Injects CSS into `<style>` element in the head of the document, or replaces CSS injected before.
+///
+/// Does nothing if there is no document (eg. when rendering on server).
+pub fn inject_style(css: &str) {
+    let (document, head) = match document() {
+        Some(document) => document,
+        None => return,
+    };
+
+    if let Some(element) = document.get_element_by_id(STYLE_ELEMENT_ID) {
+        element.set_text_content(Some(css));
+        return;
+    }
+
+    let element = match document.create_element("style") {
+        Ok(element) => element,
+        Err(error) => {
+            log::warn!("failed to create style element: {:?}", error);
+            return;
+        }
+    };
+
+    element.set_id(STYLE_ELEMENT_ID);
+    element.set_text_content(Some(css));
+
+    if let Err(error) = head.append_child(&element) {
+        log::warn!("failed to append style element: {:?}", error);
+    }
+}
+
+/// Injects `<link>` element loading CSS file into the head of the document.
+///
+/// Does nothing if there is no document (eg. when rendering on server).
+pub fn inject_link(url: &str) {
+    let (document, head) = match document() {
+        Some(document) => document,
+        None => return,
+    };
+
+    let element = match document.create_element("link") {
+        Ok(element) => element,
+        Err(error) => {
+            log::warn!("failed to create link element: {:?}", error);
+            return;
+        }
+    };
+
+    let attributes = element
+        .set_attribute("rel", "stylesheet")
+        .and_then(|_| element.set_attribute("href", url));
+
+    if let Err(error) = attributes.and_then(|_| head.append_child(&element)) {
+        log::warn!("failed to append link element for {}: {:?}", url, error);
+    }
+}
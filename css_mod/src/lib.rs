@@ -1,15 +1,28 @@
 #![warn(clippy::unwrap_used)]
 
+mod bundle;
+mod cache;
+mod chunks;
 mod compiler;
+#[cfg(feature = "dom")]
+#[doc(hidden)]
+pub mod dom;
 mod macros;
 mod mapping;
+mod naming;
 mod parsing;
 mod utils;
+mod watcher;
 
-pub use compiler::Compiler;
+pub use bundle::bundle_css;
 #[doc(hidden)]
-pub use mapping::get_mapping;
+pub use bundle::set_bundle_css;
 #[doc(hidden)]
-pub use mapping::Mappings;
-#[doc(hidden)]
-pub use mapping::MAPPINGS;
+pub use chunks::load_chunks;
+pub use compiler::Compiler;
+pub use css_mod_macros::{get, module, try_get};
+pub use mapping::ClassNames;
+pub use mapping::Mapping;
+pub use mapping::MappingError;
+pub use naming::Naming;
+pub use parsing::ast::Mode;
@@ -2,17 +2,47 @@
 // https://github.com/rust-lang/rust/issues/53749
 #[macro_export]
 #[doc(hidden)]
-macro_rules! MAPPINGS_FILE_NAME {
+macro_rules! BUNDLE_FILE_NAME {
     () => {
-        "css_mod_mappings.rs"
+        "css_mod_bundle.css"
     };
 }
 
+#[macro_export]
+#[doc(hidden)]
+macro_rules! INJECTED_FILE_NAME {
+    () => {
+        "css_mod_injected.css"
+    };
+}
+
+/// Registers CSS bundle embedded into binary, so it can be retrieved with
+/// [`css_mod::bundle_css`](fn@crate::bundle_css).
+///
+/// Name mappings do not need initialization, since they are resolved at compile time by
+/// [`css_mod::get!`](crate::get) and other macros.
+///
+/// With `dom` feature enabled, also injects CSS bundle into the document.
+#[cfg(not(feature = "dom"))]
+#[macro_export]
+macro_rules! init {
+    () => {{
+        ::css_mod::set_bundle_css(::css_mod::bundle_css!());
+    }};
+}
+
 #[allow(clippy::needless_doctest_main)]
-/// Initializes CSS name mappings which later can be retreived with [`css_mod::get!`](crate::get).
+/// Injects CSS bundle generated by [`css_mod::Compiler`](crate::Compiler) into `<style>` element
+/// in the head of the document, and registers it so it can be retrieved with
+/// [`css_mod::bundle_css`](fn@crate::bundle_css).
+///
+/// CSS bundle is embedded into binary, so styles work without serving bundle file separately.
+/// Can be called multiple times, since the same `<style>` element is reused.
 ///
-/// Should be called once in a lifetime of the program before first call to `css_mod::get!()`.
-/// Expects mappings where already generated by [`css_mod::Compiler`](crate::Compiler) in build script.
+/// Does nothing if CSS is split into chunks with
+/// [`Compiler::split_chunks`](crate::Compiler::split_chunks), since chunks of CSS modules are
+/// loaded on first request of their mappings instead, or if mappings are built separately and
+/// used with [`Compiler::use_mappings`](crate::Compiler::use_mappings).
 ///
 /// # Example
 ///
@@ -23,52 +53,50 @@ macro_rules! MAPPINGS_FILE_NAME {
 ///     css_mod::init!();
 /// }
 /// ```
+#[cfg(feature = "dom")]
 #[macro_export]
 macro_rules! init {
     () => {{
-        let mappings = include!(concat!(
+        ::css_mod::set_bundle_css(::css_mod::bundle_css!());
+
+        let css: &str = include_str!(concat!(
             env!(
                 "OUT_DIR",
                 "OUT_DIR environment variable was not found. \
                     Help: setup css_mod::Compiler in build.rs"
             ),
             "/",
-            ::css_mod::MAPPINGS_FILE_NAME!()
+            ::css_mod::INJECTED_FILE_NAME!()
         ));
 
-        // cannot add friendly error message if mappings file doesn't not exist since include!()
-        // does not support custom error messages. mappings file can be absent eg. when user adds
-        // build.rs to their project but does not call compiler in it.
-        // fix for this is to create custom proc_macro which includes mappings and produces good
-        // error message, but i don't want to bother with separate proc_macro crate only because
-        // of error messages.
-
-        ::css_mod::MAPPINGS.set(mappings).expect(
-            "Mappings were already initialized before. \
-                Help: call css_mod::init!() once early (eg. in main.rs)",
-        );
+        if !css.is_empty() {
+            ::css_mod::dom::inject_style(css);
+        }
     }};
 }
 
-/// Gets name mapping for CSS module.
-///
-/// Expects mappings were initialized earlier with [`css_mod::init!`](crate::init).
-///
-/// # Arguments
+/// Gets CSS bundle generated by [`css_mod::Compiler`](crate::Compiler) as a string embedded into
+/// binary.
 ///
-/// * `file_path`: relative path to CSS module in posix-style (ie. with forward slash separators).
+/// Same as [`css_mod::bundle_css`](fn@crate::bundle_css), but does not require
+/// [`css_mod::init!`](crate::init) to be called first.
 ///
 /// # Example
 ///
-/// ```no_run
-/// // my-component.rs
-///
-/// let css = css_mod::get!("my-component.css");
-/// let global_class_name = css["local-class-name"];
+/// ```ignore
+/// let css: &'static str = css_mod::bundle_css!();
 /// ```
 #[macro_export]
-macro_rules! get {
-    ($file_path:expr) => {{
-        ::css_mod::get_mapping(file!(), $file_path)
-    }};
+macro_rules! bundle_css {
+    () => {
+        include_str!(concat!(
+            env!(
+                "OUT_DIR",
+                "OUT_DIR environment variable was not found. \
+                    Help: setup css_mod::Compiler in build.rs"
+            ),
+            "/",
+            ::css_mod::BUNDLE_FILE_NAME!()
+        ))
+    };
 }
@@ -1,70 +1,212 @@
-use crate::utils::resolve_module_file_path;
-use once_cell::sync::OnceCell;
-use std::{collections::HashMap, ops::Index, panic};
+use std::{borrow::Cow, fmt, ops::Index, panic};
+use thiserror::Error;
+
+/// Error of getting name mapping for CSS module.
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum MappingError {
+    #[error("CSS module was not found: {0:?}")]
+    ModuleNotFound(String),
+}
 
 /// Mapping of original local names to transformed global names for particular CSS module.
 #[derive(Default, Debug)]
 pub struct Mapping<'m> {
-    names: HashMap<&'m str, &'m str>,
+    // pairs of local and global names sorted by local names, so they can be embedded into binary
+    // as static table and looked up with binary search
+    names: &'m [(&'m str, &'m str)],
     css_module_path: &'m str,
+    css_text: Option<&'m str>,
+}
+
+impl<'m> Mapping<'m> {
+    /// Creates mapping from pairs of local and global names, which should be sorted by local names.
+    #[doc(hidden)]
+    pub const fn new(css_module_path: &'m str, names: &'m [(&'m str, &'m str)]) -> Self {
+        Mapping {
+            names,
+            css_module_path,
+            css_text: None,
+        }
+    }
+
+    /// Sets CSS of module embedded into binary.
+    #[doc(hidden)]
+    pub const fn with_css_text(mut self, css_text: &'m str) -> Self {
+        self.css_text = Some(css_text);
+        self
+    }
+
+    fn find(&self, local_name: &str) -> Option<&(&'m str, &'m str)> {
+        self.names
+            .binary_search_by_key(&local_name, |(local_name, _)| local_name)
+            .ok()
+            .map(|index| &self.names[index])
+    }
+
+    /// Gets global name for local name, or `None` if there is no such name in CSS module.
+    pub fn get(&self, local_name: &str) -> Option<&'m str> {
+        self.find(local_name).map(|(_, global_name)| *global_name)
+    }
+
+    /// Checks whether CSS module has local name.
+    pub fn contains(&self, local_name: &str) -> bool {
+        self.find(local_name).is_some()
+    }
+
+    /// Iterates over pairs of local and global names in order of local names.
+    pub fn iter(&self) -> impl Iterator<Item = (&'m str, &'m str)> + '_ {
+        self.names.iter().copied()
+    }
+
+    /// Gets number of local names in CSS module.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Checks whether CSS module has no local names.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Gets CSS of module preceded with CSS of modules it imports or composes from.
+    ///
+    /// Useful when styles should be applied as a string (eg. inside shadow root).
+    ///
+    /// Requires embedding to be enabled with [`Compiler::embed_css`](crate::Compiler::embed_css)
+    /// in build script, otherwise always returns `None`.
+    pub fn css_text(&self) -> Option<&'m str> {
+        self.css_text
+    }
+
+    /// Builds list of global class names for local names which are enabled by their conditions.
+    ///
+    /// Panics if any of local names (even disabled one) was not found in CSS module, same as
+    /// indexing does.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let css = css_mod::get!("todo.css");
+    /// let class = css
+    ///     .cx([("root", true), ("completed", completed)])
+    ///     .extra(Some("external-class"))
+    ///     .to_string(); // todo__root__1a2b3c4d todo__completed__5e6f7a8b external-class
+    /// ```
+    pub fn cx<'n>(&self, names: impl IntoIterator<Item = (&'n str, bool)>) -> ClassNames<'m> {
+        ClassNames {
+            names: names
+                .into_iter()
+                .map(|(local_name, enabled)| (self[local_name], enabled))
+                .filter(|(_, enabled)| *enabled)
+                .map(|(global_name, _)| Cow::Borrowed(global_name))
+                .collect(),
+        }
+    }
 }
 
 impl<'m> Index<&str> for Mapping<'m> {
     type Output = &'m str;
 
     fn index<'i>(&self, local_name: &'i str) -> &&'m str {
-        self.names.get(local_name).unwrap_or_else(|| {
-            panic!(
-                r#"Name "{}" was not found in {:?}"#,
-                local_name, self.css_module_path
-            )
-        })
+        self.find(local_name)
+            .map(|(_, global_name)| global_name)
+            .unwrap_or_else(|| {
+                panic!(
+                    r#"Name "{}" was not found in {:?}"#,
+                    local_name, self.css_module_path
+                )
+            })
     }
 }
 
-#[derive(Default, Debug)]
-pub struct Mappings<'ms> {
-    map: HashMap<&'ms str, Mapping<'ms>>,
-    is_windows_host: bool,
+/// List of class names built with [`Mapping::cx`], which renders them separated with spaces.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClassNames<'c> {
+    names: Vec<Cow<'c, str>>,
 }
 
-impl<'ms> Mappings<'ms> {
-    pub fn is_windows_host(mut self, val: bool) -> Self {
-        self.is_windows_host = val;
+impl<'c> ClassNames<'c> {
+    /// Adds external class names (eg. passed through component properties), skipping empty ones.
+    ///
+    /// Accepts any iterable of class names, including `Option`.
+    pub fn extra(mut self, names: impl IntoIterator<Item = impl Into<Cow<'c, str>>>) -> Self {
+        self.names.extend(
+            names
+                .into_iter()
+                .map(Into::into)
+                .filter(|name| !name.trim().is_empty()),
+        );
         self
     }
+}
 
-    pub fn add_mapping(
-        mut self,
-        css_module_path: &'ms str,
-        names: impl IntoIterator<Item = (&'ms str, &'ms str)>,
-    ) -> Self {
-        self.map.insert(
-            css_module_path,
-            Mapping {
-                names: names.into_iter().collect(),
-                css_module_path,
-            },
-        );
-        self
+impl<'c> fmt::Display for ClassNames<'c> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for (index, name) in self.names.iter().enumerate() {
+            if index > 0 {
+                formatter.write_str(" ")?;
+            }
+            formatter.write_str(name.trim())?;
+        }
+        Ok(())
     }
 }
 
-pub static MAPPINGS: OnceCell<Mappings> = OnceCell::new();
+impl<'c> From<ClassNames<'c>> for String {
+    fn from(class_names: ClassNames<'c>) -> Self {
+        class_names.to_string()
+    }
+}
 
-pub fn get_mapping<'g>(source_path: &str, css_module_path: &str) -> &'g Mapping<'g> {
-    let mappings = MAPPINGS.get().expect(
-        "Mappings are not initialized. Help: call css_mod::init!() once early (eg. in main.rs)",
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // TODO: resolve CSS module paths when compiling, not on performance critical runtime path.
-    // try to move it to proc_macro when `proc_macro::Span` is stabilized
-    // https://github.com/rust-lang/rust/issues/54725
-    let module_file_path =
-        resolve_module_file_path(source_path, css_module_path, mappings.is_windows_host);
+    #[test]
+    fn gets_names() {
+        let mapping = Mapping::new("src/a.css", &[("a", "a__1"), ("b", "b__2")]);
 
-    mappings
-        .map
-        .get(&module_file_path as &str)
-        .unwrap_or_else(|| panic!("CSS module was not found: {:?}", module_file_path))
+        assert_eq!(mapping.get("a"), Some("a__1"));
+        assert_eq!(mapping.get("c"), None);
+        assert!(mapping.contains("b"));
+        assert!(!mapping.contains("c"));
+        assert_eq!(mapping.len(), 2);
+        assert!(!mapping.is_empty());
+
+        assert_eq!(
+            mapping.iter().collect::<Vec<_>>(),
+            [("a", "a__1"), ("b", "b__2")]
+        );
+        assert_eq!(mapping["b"], "b__2");
+        assert!(Mapping::new("src/b.css", &[]).is_empty());
+        assert_eq!(mapping.css_text(), None);
+        assert_eq!(
+            Mapping::new("src/b.css", &[])
+                .with_css_text(".b {}")
+                .css_text(),
+            Some(".b {}")
+        );
+    }
+
+    #[test]
+    fn builds_class_names() {
+        let mapping = Mapping::new("src/a.css", &[("a", "a__1"), ("b", "b__2 c__3")]);
+
+        assert_eq!(mapping.cx([("a", true), ("b", false)]).to_string(), "a__1");
+        assert_eq!(
+            mapping
+                .cx([("a", true), ("b", true)])
+                .extra(Some("external"))
+                .extra([String::from("x"), String::new()])
+                .to_string(),
+            "a__1 b__2 c__3 external x"
+        );
+        assert_eq!(mapping.cx([]).extra(None::<&str>).to_string(), "");
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Name "c" was not found in "src/a.css""#)]
+    fn error_is_unknown_class_name() {
+        Mapping::new("src/a.css", &[("a", "a__1")]).cx([("c", false)]);
+    }
 }
@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Function generating global name from module path, local name and hash of module content.
+pub type NamingFn = dyn Fn(&Path, &str, u64) -> String + Send + Sync;
+
+/// Strategy of generating global names for local names of CSS modules.
+///
+/// Can be created from template string (eg. `"[name]_[local]_[hash:6]"`) or custom function with
+/// [`Naming::custom`].
+#[derive(Clone)]
+pub enum Naming {
+    /// Template with placeholders:
+    ///
+    /// * `[name]`: File stem of CSS module (eg. `todo` for `todo.css`).
+    /// * `[local]`: Local name.
+    /// * `[hash]`: Hex-encoded hash of module path and local name, which doesn't change unless
+    ///   module is moved or name is renamed. Can be truncated to particular length (eg. `[hash:6]`).
+    /// * `[contenthash]`: Hex-encoded hash of CSS module content.
+    /// * `[index]`: Sequential number of the name in stylesheet.
+    Template(String),
+    /// Shortest possible names unique across the stylesheet (eg. `a`, `b`, ..., `aB`), which
+    /// reduce size of CSS bundle and binary. Used by default in release builds, with
+    /// [`Naming::minified`].
+    ///
+    /// Names can start with prefix, which keeps them from colliding with global class names from
+    /// other stylesheets on the page (eg. `m-a`, `m-b`, ..., `m-aB` for `m-` prefix).
+    ///
+    /// Names equal to keywords (eg. `none` or `auto`) are skipped, since they cannot be used as
+    /// names of animations, grid areas or counters.
+    ///
+    /// Names are assigned sequentially, so they change when names are added to preceding modules.
+    Minified { prefix: String },
+    /// Custom function receiving module path, local name and hash of module content.
+    Custom(Arc<NamingFn>),
+}
+
+const DEFAULT_TEMPLATE: &str = "[name]__[local]__[hash:8]";
+
+// keywords which are valid values of properties referencing local names, so names equal to them
+// would not be treated as names (eg. `animation-name: none` or `grid-area: auto`)
+const RESERVED_NAMES: &[&str] = &[
+    "auto",
+    "default",
+    "inherit",
+    "initial",
+    "none",
+    "revert",
+    "revert-layer",
+    "span",
+    "unset",
+];
+
+impl Default for Naming {
+    fn default() -> Self {
+        Naming::Template(DEFAULT_TEMPLATE.into())
+    }
+}
+
+impl fmt::Debug for Naming {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Naming::Template(template) => {
+                formatter.debug_tuple("Template").field(template).finish()
+            }
+            Naming::Minified { prefix } => formatter
+                .debug_struct("Minified")
+                .field("prefix", prefix)
+                .finish(),
+            Naming::Custom(_) => formatter.debug_tuple("Custom").finish(),
+        }
+    }
+}
+
+impl PartialEq for Naming {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Naming::Template(a), Naming::Template(b)) => a == b,
+            (Naming::Minified { prefix: a }, Naming::Minified { prefix: b }) => a == b,
+            (Naming::Custom(a), Naming::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<&str> for Naming {
+    fn from(template: &str) -> Self {
+        Naming::Template(template.into())
+    }
+}
+
+impl From<String> for Naming {
+    fn from(template: String) -> Self {
+        Naming::Template(template)
+    }
+}
+
+impl Naming {
+    /// Creates naming from custom function, which receives module path, local name and hash of
+    /// module content.
+    pub fn custom(naming: impl Fn(&Path, &str, u64) -> String + Send + Sync + 'static) -> Self {
+        Naming::Custom(Arc::new(naming))
+    }
+
+    /// Creates minified naming without prefix.
+    pub fn minified() -> Self {
+        Naming::Minified {
+            prefix: String::new(),
+        }
+    }
+
+    /// Checks that template contains known placeholders only.
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self {
+            Naming::Template(_) => {
+                self.name(&NameSource {
+                    module_path: Path::new("module.css"),
+                    local: "local",
+                    hash: 0,
+                    content_hash: 0,
+                    index: 0,
+                })?;
+                Ok(())
+            }
+            Naming::Minified { .. } | Naming::Custom(_) => Ok(()),
+        }
+    }
+
+    /// Checks whether global names depend on order in which names are added to stylesheet, so
+    /// CSS modules should be transformed sequentially.
+    pub(crate) fn is_order_dependent(&self) -> bool {
+        match self {
+            Naming::Template(template) => template.contains("[index"),
+            Naming::Minified { .. } => true,
+            Naming::Custom(_) => false,
+        }
+    }
+
+    /// Checks whether generated global name should be skipped, so next name is generated
+    /// instead.
+    pub(crate) fn is_reserved(&self, name: &str) -> bool {
+        match self {
+            Naming::Minified { .. } => RESERVED_NAMES
+                .iter()
+                .any(|reserved| reserved.eq_ignore_ascii_case(name)),
+            Naming::Template(_) | Naming::Custom(_) => false,
+        }
+    }
+
+    /// Generates global name.
+    pub(crate) fn name(&self, source: &NameSource) -> Result<String> {
+        let NameSource {
+            module_path,
+            local,
+            hash,
+            content_hash,
+            index,
+        } = *source;
+
+        match self {
+            Naming::Template(template) => render(template, |placeholder, length| {
+                let value = match placeholder {
+                    "name" => module_path.file_stem()?.to_str()?.to_owned(),
+                    "local" => local.to_owned(),
+                    "hash" => format!("{:016x}", hash),
+                    "contenthash" => format!("{:016x}", content_hash),
+                    "index" => index.to_string(),
+                    _ => return None,
+                };
+
+                Some(match length {
+                    Some(length) => value.chars().take(length).collect(),
+                    None => value,
+                })
+            }),
+            Naming::Minified { prefix } => Ok(format!("{}{}", prefix, minified_name(index))),
+            Naming::Custom(naming) => Ok(naming(module_path, local, content_hash)),
+        }
+    }
+}
+
+/// Gets shortest name for given index, so names with lower indexes are never longer than names
+/// with higher indexes.
+fn minified_name(index: u64) -> String {
+    // names cannot start with digit, dash or underscore
+    const FIRST_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_-";
+
+    let mut name = String::new();
+    let mut index = index;
+
+    name.push(FIRST_CHARS[(index % FIRST_CHARS.len() as u64) as usize] as char);
+    index /= FIRST_CHARS.len() as u64;
+
+    while index > 0 {
+        index -= 1;
+        name.push(CHARS[(index % CHARS.len() as u64) as usize] as char);
+        index /= CHARS.len() as u64;
+    }
+
+    name
+}
+
+/// Values which global name is generated from.
+pub(crate) struct NameSource<'a> {
+    pub module_path: &'a Path,
+    pub local: &'a str,
+    // hash of relative module path, local name and salt
+    pub hash: u64,
+    // hash of module content
+    pub content_hash: u64,
+    pub index: u64,
+}
+
+/// Replaces placeholders in template (eg. `[local]` or `[hash:6]`) with their values.
+fn render(
+    template: &str,
+    mut value: impl FnMut(&str, Option<usize>) -> Option<String>,
+) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('[') {
+        let end = rest[start..]
+            .find(']')
+            .map(|end| start + end)
+            .ok_or_else(|| anyhow!("Unclosed placeholder in naming template: {}", template))?;
+
+        let (placeholder, length) = match rest[start + 1..end].split_once(':') {
+            Some((placeholder, length)) => (
+                placeholder,
+                Some(length.parse().map_err(|_| {
+                    anyhow!(
+                        "Invalid placeholder length in naming template: {}",
+                        template
+                    )
+                })?),
+            ),
+            None => (&rest[start + 1..end], None),
+        };
+
+        result.push_str(&rest[..start]);
+        result.push_str(&value(placeholder, length).ok_or_else(|| {
+            anyhow!(
+                "Unknown placeholder [{}] in naming template: {}",
+                placeholder,
+                template
+            )
+        })?);
+
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn source(module_path: &str) -> NameSource<'_> {
+        NameSource {
+            module_path: Path::new(module_path),
+            local: "item",
+            hash: 0x0123456789abcdef,
+            content_hash: 0xff,
+            index: 5,
+        }
+    }
+
+    #[test]
+    fn renders_template() {
+        assert_eq!(
+            Naming::from("[name]_[local]-[hash:6]_[index]")
+                .name(&source("/a/todo.css"))
+                .unwrap(),
+            "todo_item-012345_5"
+        );
+        assert_eq!(
+            Naming::from("[contenthash]")
+                .name(&source("/a/todo.css"))
+                .unwrap(),
+            "00000000000000ff"
+        );
+    }
+
+    #[test]
+    fn renders_custom() {
+        let naming = Naming::custom(|path, local, hash| {
+            format!("{}-{}-{:x}", path.display(), local.to_uppercase(), hash)
+        });
+
+        assert_eq!(naming.name(&source("a.css")).unwrap(), "a.css-ITEM-ff");
+    }
+
+    #[test]
+    fn renders_minified() {
+        let name = minified_name;
+
+        assert_eq!(name(0), "a");
+        assert_eq!(name(51), "Z");
+        assert_eq!(name(52), "aa");
+        assert_eq!(name(53), "ba");
+        assert_eq!(name(52 + 52 * 64 - 1), "Z-");
+        assert_eq!(name(52 + 52 * 64), "aaa");
+
+        let names: std::collections::HashSet<_> = (0..100_000).map(name).collect();
+        assert_eq!(names.len(), 100_000);
+    }
+
+    #[test]
+    fn renders_minified_with_prefix() {
+        assert_eq!(Naming::minified().name(&source("a.css")).unwrap(), "f");
+
+        let naming = Naming::Minified {
+            prefix: "m-".into(),
+        };
+        assert_eq!(naming.name(&source("a.css")).unwrap(), "m-f");
+        assert!(naming.is_reserved("none"));
+        assert!(naming.is_reserved("Auto"));
+        assert!(!naming.is_reserved("nonf"));
+        assert!(!Naming::from("[local]").is_reserved("none"));
+    }
+
+    #[test]
+    fn detects_order_dependent_naming() {
+        assert!(!Naming::default().is_order_dependent());
+        assert!(Naming::from("[local]_[index]").is_order_dependent());
+        assert!(Naming::minified().is_order_dependent());
+        assert!(!Naming::custom(|_, local, _| local.into()).is_order_dependent());
+    }
+
+    #[test]
+    fn error_is_invalid_template() {
+        assert!(Naming::from("[name]_[local]").validate().is_ok());
+        assert!(Naming::from("[name]_[unknown]").validate().is_err());
+        assert!(Naming::from("[hash:x]").validate().is_err());
+        assert!(Naming::from("[local").validate().is_err());
+    }
+}
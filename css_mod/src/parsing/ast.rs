@@ -1,21 +1,55 @@
-use super::parser::{self, Error, Rule};
+use super::parser::{self, close_comment, to_css, trim, Node};
+use super::tokenizer::{
+    is_ident_char, serialize_identifier, serialize_string, tokenize, Token, TokenKind,
+};
+use crate::cache::{self, Cache};
+use crate::naming::{NameSource, Naming};
+use crate::utils::{hash, mime_type, normalize_path, percent_decode};
 use anyhow::{anyhow, Context, Result};
-use pest::iterators::{Pair, Pairs};
-use std::collections::{BTreeMap, HashMap};
+use base64::Engine;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
 pub type Names = HashMap<String, String>;
 pub type Children = Vec<Child>;
+pub type Compositions = BTreeMap<String, Vec<Composition>>;
+
+/// Scoping mode for names in CSS module.
+///
+/// Can be switched for particular selectors with `:local` / `:global` pseudo-classes
+/// (eg. `:global(.foo)`, `:global .foo .bar`, `@keyframes :global(foo)`).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Names are transformed to unique global names.
+    #[default]
+    Local,
+    /// Names are left as is.
+    Global,
+}
 
-#[derive(Debug, PartialEq)]
+/// Class name composed into local class with `composes` property.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Composition {
+    /// Transformed name of local class from the same module (eg. `composes: foo`).
+    Local(String),
+    /// Global class name (eg. `composes: foo from global`).
+    Global(String),
+    /// Local class name from other module (eg. `composes: foo from "./other.css"`).
+    External { module_path: PathBuf, name: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Child {
     AtRule {
         name: Option<String>,
         rule: Option<String>,
-        children: Children,
+        // block of the rule, or none if rule is terminated with semicolon (eg. `@import "a.css";`)
+        children: Option<Children>,
     },
     Comment {
         value: Option<String>,
@@ -30,6 +64,12 @@ pub enum Child {
     },
 }
 
+impl Child {
+    fn is_import(&self) -> bool {
+        matches!(self, Child::AtRule { name: Some(name), .. } if name == "import")
+    }
+}
+
 impl fmt::Display for Child {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -38,29 +78,23 @@ impl fmt::Display for Child {
                 rule,
                 children,
             } => {
-                if let (Some(name), Some(rule)) = (name, rule) {
-                    if children.is_empty() {
-                        write!(formatter, "@{} {}; ", name, rule.trim())?;
-                    } else {
-                        write!(formatter, "@{} {} {{ ", name, rule.trim())?;
-
-                        for child in children {
-                            write!(formatter, "{}", child)?;
-                        }
+                if let Some(name) = name {
+                    write!(formatter, "@{}", name)?;
 
-                        writeln!(formatter, "}}")?;
+                    if let Some(rule) = rule {
+                        write!(formatter, " {}", rule.trim())?;
                     }
-                } else if let Some(name) = name {
-                    if children.is_empty() {
-                        write!(formatter, "@{};", name)?;
-                    } else {
-                        write!(formatter, "@{} {{ ", name)?;
+
+                    if let Some(children) = children {
+                        write!(formatter, " {{ ")?;
 
                         for child in children {
                             write!(formatter, "{}", child)?;
                         }
 
                         writeln!(formatter, "}}")?;
+                    } else {
+                        write!(formatter, "; ")?;
                     }
                 }
             }
@@ -86,7 +120,8 @@ impl fmt::Display for Child {
             }
             Child::Comment { value } => {
                 if let Some(value) = value {
-                    write!(formatter, "{}", value)?;
+                    // separate legacy hacks from following declarations and closing braces
+                    write!(formatter, "{} ", value.trim_end())?;
                 }
             }
         };
@@ -101,25 +136,69 @@ pub struct ParserContext<'c> {
     pub name: &'c str,
     pub absolute_path: &'c PathBuf,
     pub stylesheet: &'c mut Stylesheet,
+    // hash of module content
+    pub content_hash: u64,
+    pub mode: Mode,
+    // transformed names of classes from selector of current rule, if that selector consists of
+    // single local class selectors only (eg. `.foo` or `.foo, .bar`)
+    pub rule_classes: Option<Vec<String>>,
+    // whether current node is nested in block of other rule
+    pub is_nested: bool,
+    // lowercase name of at-rule, which block directly contains current node (eg. "media")
+    pub at_rule: Option<String>,
+    // local names of classes defined in selectors of module or imported from other modules
+    pub classes: HashSet<String>,
+    // local names of classes composed from the same module, which should be defined in it
+    pub composed_classes: Vec<String>,
+    // names of grid areas defined in module, which create implicit lines with "-start" and "-end"
+    // suffixes
+    pub grid_areas: HashSet<String>,
 }
 
 impl<'c> ParserContext<'c> {
-    fn add_name(&mut self, name: String) -> String {
-        let res = self.module.names.entry(name.clone()).or_insert(format!(
-            "{}__{}__{}",
-            &self.name, &name, &self.stylesheet.names_count
-        ));
+    fn add_name(&mut self, name: String) -> Result<String> {
+        if let Some(global_name) = self.module.names.get(&name) {
+            return Ok(global_name.clone());
+        }
+
+        let global_name = loop {
+            let global_name = self.stylesheet.naming.name(&NameSource {
+                module_path: &self.module.file_path,
+                local: &name,
+                hash: self.stylesheet.name_hash(&self.module.file_path, &name),
+                content_hash: self.content_hash,
+                index: self.stylesheet.names_count,
+            })?;
+
+            self.stylesheet.names_count += 1;
+
+            if !self.stylesheet.naming.is_reserved(&global_name) {
+                break global_name;
+            }
+        };
+        self.module.names.insert(name, global_name.clone());
 
-        self.stylesheet.names_count += 1;
+        Ok(global_name)
+    }
 
-        res.to_owned()
+    /// Adds local name, and gets its transformed name serialized as CSS identifier.
+    fn add_ident(&mut self, name: &str) -> Result<String> {
+        Ok(serialize_identifier(&self.add_name(name.into())?))
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Module {
     pub children: Children,
     pub names: Names,
+    // compositions of transformed local class names
+    pub compositions: Compositions,
+    // modules imported unconditionally, which should precede this module in CSS bundle
+    pub imports: Vec<PathBuf>,
+    // modules imported with conditions (eg. `@import "a.css" screen;`), which are rendered inplace
+    pub conditional_imports: Vec<PathBuf>,
+    // asset files referenced with `url()`, mapped to output file names, or none if inlined
+    pub assets: BTreeMap<PathBuf, Option<String>>,
     pub file_path: PathBuf,
 }
 
@@ -134,6 +213,10 @@ impl Default for Module {
         Self {
             children: Vec::new(),
             names: HashMap::new(),
+            compositions: Compositions::new(),
+            imports: Vec::new(),
+            conditional_imports: Vec::new(),
+            assets: BTreeMap::new(),
             file_path: path,
         }
     }
@@ -151,11 +234,37 @@ impl fmt::Display for Module {
 }
 
 impl<'m> Module {
+    /// Gets paths of modules which should precede this module in CSS bundle.
+    fn dependencies(&self) -> impl Iterator<Item = &PathBuf> {
+        self.imports.iter().chain(
+            self.compositions
+                .values()
+                .flatten()
+                .filter_map(|composition| match composition {
+                    Composition::External { module_path, .. } => Some(module_path),
+                    _ => None,
+                }),
+        )
+    }
+
     pub fn new(stylesheet: &mut Stylesheet, file_path: PathBuf, input: &'m str) -> Result<Self> {
-        let pairs = parser::stylesheet(input)?;
+        Self::from_nodes(stylesheet, file_path, input, parser::stylesheet(input)?)
+    }
+
+    /// Transforms nodes parsed from module content.
+    fn from_nodes(
+        stylesheet: &mut Stylesheet,
+        file_path: PathBuf,
+        input: &'m str,
+        nodes: Vec<Node>,
+    ) -> Result<Self> {
         let mut module = Module {
             children: Children::new(),
             names: Names::new(),
+            compositions: Compositions::new(),
+            imports: Vec::new(),
+            conditional_imports: Vec::new(),
+            assets: BTreeMap::new(),
             file_path: file_path.clone(),
         };
         let mut context = ParserContext {
@@ -169,48 +278,476 @@ impl<'m> Module {
                 .parent()
                 .context("No parent directory")?
                 .to_path_buf(),
+            content_hash: hash(input.as_bytes()),
+            mode: stylesheet.mode(&file_path),
+            rule_classes: None,
+            is_nested: false,
+            at_rule: None,
+            classes: HashSet::new(),
+            composed_classes: Vec::new(),
+            grid_areas: grid_areas(&nodes),
             stylesheet,
         };
 
-        for pair in pairs {
-            let child = match pair.as_rule() {
-                Rule::comment => comment(pair),
-                Rule::atrule => atrule(&mut context, pair)?,
-                Rule::selectrule => selectrule(&mut context, pair)?,
-                Rule::EOI => None,
-                _ => return Err(anyhow!(Error::from(pair))),
-            };
-
-            if let Some(child) = child {
+        for node in nodes {
+            if let Some(child) = child(&mut context, node)? {
                 context.module.children.push(child);
             }
         }
 
+        // classes can be composed before they are defined, so check them once whole module is
+        // transformed
+        if let Some(name) = context
+            .composed_classes
+            .iter()
+            .find(|name| !context.classes.contains(*name))
+        {
+            return Err(anyhow!(
+                r#"Composed class "{}" is not defined in module"#,
+                name
+            ));
+        }
+
         Ok(module)
     }
 }
 
+/// CSS module loaded on thread pool.
+enum LoadedModule {
+    Transformed { module: Module, content_hash: u64 },
+    Pending(PendingModule),
+}
+
+/// CSS module which was parsed, but cannot be transformed until modules it imports are.
+struct PendingModule {
+    input: String,
+    imports: Vec<PathBuf>,
+}
+
 #[derive(Debug, Default, PartialEq)]
 pub struct Stylesheet {
     // use sorted map instead of hash map so output bundle is rendered deterministically with the
     // same content across recompilations
     pub modules: BTreeMap<PathBuf, Module>,
+    // modules added directly, rather than imported or composed from other modules
+    pub entries: BTreeSet<PathBuf>,
+    // chain of modules which are currently being parsed, to detect import cycles
+    pub import_chain: Vec<PathBuf>,
+    // number of global names generated so far
     pub names_count: u64,
+    pub default_mode: Mode,
+    pub module_modes: BTreeMap<PathBuf, Mode>,
+    pub naming: Naming,
+    // salt mixed into hashes of global names
+    pub naming_salt: String,
+    // directory which module paths are relative to in hashes of global names, so names don't
+    // depend on location of the project
+    pub base_dir: PathBuf,
+    // asset files referenced with `url()`, mapped to output file names
+    pub assets: BTreeMap<PathBuf, String>,
+    // max size of asset file in bytes to inline it into CSS as data URI instead of copying
+    pub inline_assets_limit: Option<u64>,
+    // transformed modules from previous compilation
+    pub cache: Option<Cache>,
+    // hashes of contents modules were transformed from, which may differ from current contents
+    // of module files if they were changed during compilation
+    pub content_hashes: BTreeMap<PathBuf, u64>,
 }
 
 impl Stylesheet {
+    /// Gets scoping mode for CSS module.
+    pub fn mode(&self, module_path: &Path) -> Mode {
+        self.module_modes
+            .get(module_path)
+            .copied()
+            .unwrap_or(self.default_mode)
+    }
+
+    /// Gets hash of global name, which only depends on module path relative to base directory,
+    /// local name and salt.
+    fn name_hash(&self, module_path: &Path, local: &str) -> u64 {
+        let relative_path = module_path
+            .strip_prefix(&self.base_dir)
+            .unwrap_or(module_path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        hash(format!("{}\0{}\0{}", relative_path, local, self.naming_salt).as_bytes())
+    }
+
+    /// Adds CSS module to stylesheet, along with modules it imports or composes from.
     pub fn add_module(&mut self, module_path: &Path) -> Result<&Module> {
-        let mut file = File::open(module_path)?;
-        let mut input = String::new();
+        self.entries.insert(module_path.to_path_buf());
+        self.load_module(module_path)
+    }
+
+    /// Parses CSS module, unless it was already parsed before.
+    fn load_module(&mut self, module_path: &Path) -> Result<&Module> {
+        if !self.modules.contains_key(module_path) {
+            if self.import_chain.iter().any(|path| path == module_path) {
+                let chain = self
+                    .import_chain
+                    .iter()
+                    .skip_while(|path| *path != module_path)
+                    .chain([&module_path.to_path_buf()])
+                    .map(|path| format!("{:?}", path))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+
+                return Err(anyhow!("Import cycle detected: {}", chain));
+            }
+
+            let mut file = File::open(module_path)
+                .with_context(|| format!("Failed to open CSS module: {:?}", module_path))?;
+            let mut input = String::new();
+
+            file.read_to_string(&mut input)?;
+            self.content_hashes
+                .insert(module_path.to_path_buf(), hash(input.as_bytes()));
+
+            let cached = self
+                .cache
+                .as_mut()
+                .and_then(|cache| cache.take(module_path, &input));
+
+            let module = match cached {
+                Some(module) => {
+                    log::debug!("use cached css module: {:?}", module_path);
+                    self.add_assets(&module);
+                    Ok(module)
+                }
+                None => {
+                    self.import_chain.push(module_path.to_path_buf());
+                    let module = Module::new(self, module_path.to_path_buf(), &input);
+                    self.import_chain.pop();
+                    module
+                }
+            };
+
+            self.modules.insert(module_path.to_path_buf(), module?);
+            self.add_dependencies(module_path)?;
+        }
+
+        self.modules
+            .get(module_path)
+            .context("Failed to add module")
+    }
+
+    /// Adds modules which given module imports or composes from, if they were not added yet.
+    fn add_dependencies(&mut self, module_path: &Path) -> Result<()> {
+        // conditional imports are only loaded here if module was taken from cache, otherwise they
+        // were loaded while parsing the module
+        let dependencies: Vec<PathBuf> = self
+            .modules
+            .get(module_path)
+            .map(|module| {
+                module
+                    .dependencies()
+                    .chain(&module.conditional_imports)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for dependency in dependencies {
+            self.load_module(&dependency)
+                .with_context(|| format!("Failed to add module: {:?}", dependency))?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds asset files referenced by module, which was transformed outside of this stylesheet.
+    fn add_assets(&mut self, module: &Module) {
+        for (asset_path, file_name) in &module.assets {
+            if let Some(file_name) = file_name {
+                self.assets.insert(asset_path.clone(), file_name.clone());
+            }
+        }
+    }
+
+    /// Adds CSS modules to stylesheet, along with modules they import or compose from.
+    ///
+    /// Modules are parsed and transformed on thread pool, unless global names depend on order in
+    /// which names are added to stylesheet. Either way, stylesheet ends up the same as if modules
+    /// were added one by one.
+    pub fn add_modules<'p>(
+        &mut self,
+        module_paths: impl IntoIterator<Item = &'p PathBuf>,
+    ) -> Result<()> {
+        let context = |module_path: &Path| {
+            format!(
+                "Failed to parse and transform CSS module: {:?}",
+                module_path
+            )
+        };
+
+        if self.naming.is_order_dependent() {
+            for module_path in module_paths {
+                self.add_module(module_path)
+                    .with_context(|| context(module_path))?;
+            }
+
+            return Ok(());
+        }
+
+        let mut discovered: BTreeSet<PathBuf> = module_paths.into_iter().cloned().collect();
+        self.entries.extend(discovered.iter().cloned());
+
+        // modules which were parsed, but wait for modules they import to be transformed
+        let mut pending: BTreeMap<PathBuf, PendingModule> = BTreeMap::new();
+
+        loop {
+            discovered
+                .retain(|path| !self.modules.contains_key(path) && !pending.contains_key(path));
+
+            let ready: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, module)| {
+                    module
+                        .imports
+                        .iter()
+                        .all(|path| self.modules.contains_key(path))
+                })
+                .map(|(path, _)| path.clone())
+                .collect();
+
+            let mut tasks = Vec::new();
+
+            for module_path in std::mem::take(&mut discovered) {
+                let cached = self
+                    .cache
+                    .as_mut()
+                    .and_then(|cache| cache.remove(&module_path));
+                tasks.push((module_path, None, cached));
+            }
+
+            for module_path in ready {
+                let input = pending.remove(&module_path).map(|module| module.input);
+                tasks.push((module_path, input, None));
+            }
+
+            if tasks.is_empty() {
+                // remaining modules import each other, so load them one by one to report cycle
+                for module_path in pending.keys() {
+                    self.load_module(module_path)
+                        .with_context(|| context(module_path))?;
+                }
+
+                return Ok(());
+            }
+
+            let stylesheet = &*self;
+            let results: Vec<_> = tasks
+                .into_par_iter()
+                .map(|(module_path, input, cached)| {
+                    let result = stylesheet.load_module_detached(&module_path, input, cached);
+                    (module_path, result)
+                })
+                .collect();
+
+            // merge results in the same order regardless of which thread finished first
+            for (module_path, result) in results {
+                match result.with_context(|| context(&module_path))? {
+                    LoadedModule::Transformed {
+                        module,
+                        content_hash,
+                    } => {
+                        self.add_assets(&module);
+                        self.content_hashes
+                            .insert(module_path.clone(), content_hash);
+                        discovered.extend(
+                            module
+                                .dependencies()
+                                .chain(&module.conditional_imports)
+                                .cloned(),
+                        );
+                        self.modules.insert(module_path, module);
+                    }
+                    LoadedModule::Pending(module) => {
+                        discovered.extend(module.imports.iter().cloned());
+                        pending.insert(module_path, module);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses and transforms CSS module without changing the stylesheet, so it can run on other
+    /// thread. Module is only transformed when modules it imports were already added to
+    /// stylesheet, and is returned as pending otherwise.
+    fn load_module_detached(
+        &self,
+        module_path: &Path,
+        input: Option<String>,
+        cached: Option<cache::Entry>,
+    ) -> Result<LoadedModule> {
+        let input = match input {
+            Some(input) => input,
+            None => fs::read_to_string(module_path)
+                .with_context(|| format!("Failed to open CSS module: {:?}", module_path))?,
+        };
+
+        let content_hash = hash(input.as_bytes());
+
+        if let Some(module) = cached.and_then(|cached| cached.into_module(&input)) {
+            log::debug!("use cached css module: {:?}", module_path);
+            return Ok(LoadedModule::Transformed {
+                module,
+                content_hash,
+            });
+        }
+
+        let nodes = parser::stylesheet(&input)?;
+        let mut imports = Vec::new();
+        let absolute_path = module_path.parent().context("No parent directory")?;
+        import_paths(&nodes, absolute_path, &mut imports);
+
+        if !imports.iter().all(|path| self.modules.contains_key(path)) {
+            return Ok(LoadedModule::Pending(PendingModule { input, imports }));
+        }
 
-        file.read_to_string(&mut input)?;
+        // transform module within separate stylesheet, which has the same options and modules it
+        // imports
+        let mut stylesheet = Stylesheet {
+            modules: imports
+                .iter()
+                .filter_map(|path| Some((path.clone(), self.modules.get(path)?.clone())))
+                .collect(),
+            import_chain: vec![module_path.to_path_buf()],
+            default_mode: self.default_mode,
+            module_modes: self.module_modes.clone(),
+            naming: self.naming.clone(),
+            naming_salt: self.naming_salt.clone(),
+            base_dir: self.base_dir.clone(),
+            inline_assets_limit: self.inline_assets_limit,
+            ..Stylesheet::default()
+        };
+
+        Ok(LoadedModule::Transformed {
+            module: Module::from_nodes(&mut stylesheet, module_path.to_path_buf(), &input, nodes)?,
+            content_hash,
+        })
+    }
+
+    /// Gets modules in order they should be rendered to CSS bundle, so modules are preceded with
+    /// modules they import or compose from.
+    ///
+    /// Modules imported with conditions are rendered inplace of their imports only, even if they
+    /// were added directly too (eg. matched by the same glob pattern as importing module), so
+    /// their rules are not applied regardless of conditions.
+    pub fn ordered_modules(&self) -> Vec<&Module> {
+        let conditional_imports: HashSet<&PathBuf> = self
+            .modules
+            .values()
+            .flat_map(|module| &module.conditional_imports)
+            .collect();
+
+        let mut visited = HashSet::new();
+        let mut ordered = Vec::new();
+
+        for entry in &self.entries {
+            if !conditional_imports.contains(entry) {
+                self.order_module(entry, &mut visited, &mut ordered);
+            }
+        }
+
+        ordered
+    }
+
+    /// Gets module along with modules it imports or composes from (directly or transitively), in
+    /// order they should be rendered to CSS chunks.
+    pub fn module_with_dependencies<'s>(&'s self, module_path: &'s Path) -> Vec<&'s Module> {
+        let mut ordered = Vec::new();
+        self.order_module(module_path, &mut HashSet::new(), &mut ordered);
+        ordered
+    }
+
+    fn order_module<'s>(
+        &'s self,
+        module_path: &'s Path,
+        visited: &mut HashSet<&'s Path>,
+        ordered: &mut Vec<&'s Module>,
+    ) {
+        if !visited.insert(module_path) {
+            return;
+        }
+
+        if let Some(module) = self.modules.get(module_path) {
+            for dependency in module.dependencies() {
+                self.order_module(dependency, visited, ordered);
+            }
 
-        let module = Module::new(self, module_path.to_path_buf(), &input)?;
+            ordered.push(module);
+        }
+    }
 
-        Ok(self
+    /// Gets names exported by CSS module.
+    ///
+    /// Each local name is mapped to space-separated list of global names, which starts with its
+    /// own transformed name and continues with names of classes composed into it.
+    pub fn exports(&self, module: &Module) -> Result<BTreeMap<String, String>> {
+        let compositions: HashMap<&str, &Vec<Composition>> = self
             .modules
-            .entry(module_path.to_path_buf())
-            .or_insert(module))
+            .values()
+            .flat_map(|module| module.compositions.iter())
+            .map(|(name, compositions)| (name.as_str(), compositions))
+            .collect();
+
+        let mut exports = BTreeMap::new();
+
+        for (local_name, global_name) in &module.names {
+            let mut global_names = Vec::new();
+            self.compose(&compositions, global_name, &mut global_names)?;
+            exports.insert(local_name.clone(), global_names.join(" "));
+        }
+
+        Ok(exports)
+    }
+
+    fn compose<'s>(
+        &'s self,
+        compositions: &HashMap<&str, &'s Vec<Composition>>,
+        global_name: &'s str,
+        global_names: &mut Vec<&'s str>,
+    ) -> Result<()> {
+        // skip names which were already composed, which also prevents infinite recursion for
+        // cyclic compositions
+        if global_names.contains(&global_name) {
+            return Ok(());
+        }
+
+        global_names.push(global_name);
+
+        for composition in compositions.get(global_name).copied().into_iter().flatten() {
+            match composition {
+                Composition::Local(name) => {
+                    self.compose(compositions, name, global_names)?;
+                }
+                Composition::Global(name) => {
+                    if !global_names.contains(&name.as_str()) {
+                        global_names.push(name);
+                    }
+                }
+                Composition::External { module_path, name } => {
+                    let name = self
+                        .modules
+                        .get(module_path)
+                        .with_context(|| format!("Module was not found: {:?}", module_path))?
+                        .names
+                        .get(name)
+                        .with_context(|| {
+                            format!(r#"Name "{}" was not found in {:?}"#, name, module_path)
+                        })?;
+
+                    self.compose(compositions, name, global_names)?;
+                }
+            }
+        }
+
+        Ok(())
     }
 
     #[cfg(test)]
@@ -221,170 +758,1153 @@ impl Stylesheet {
         let path = PathBuf::from_str(file!()).unwrap();
         let module = Module::new(self, path.clone(), input)?;
 
-        Ok(self.modules.entry(path).or_insert(module))
+        self.entries.insert(path.clone());
+        self.modules.entry(path.clone()).or_insert(module);
+        self.add_dependencies(&path)?;
+
+        Ok(self.modules.get(&path).unwrap())
     }
 }
 
-pub fn atrule<'t>(context: &mut ParserContext, pair: Pair<'t, Rule>) -> Result<Option<Child>> {
-    let mut name: Option<String> = None;
-    let mut rule: Option<String> = None;
-    let mut children = Vec::new();
+impl fmt::Display for Stylesheet {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let modules = self.ordered_modules();
 
-    for pair in pair.into_inner() {
-        let child = match pair.as_rule() {
-            Rule::identifier => {
-                name = Some(pair.as_str().into());
-                None
+        // `@import` rules are only valid at the beginning of stylesheet, so hoist external imports
+        // from all modules to the top of CSS bundle
+        for module in &modules {
+            for child in module.children.iter().filter(|child| child.is_import()) {
+                write!(formatter, "{}", child)?;
             }
-            Rule::atrule_rule => {
-                if Some("keyframes".into()) == name {
-                    rule = Some((&context.add_name(pair.as_str().trim().into())).to_string());
-                } else if Some("import".into()) == name {
-                    let quotes: &[_] = &['"', '\''];
-                    let path = context
-                        .absolute_path
-                        .clone()
-                        .join(pair.as_str().trim_matches(quotes));
-                    let import = context.stylesheet.add_module(&path)?;
-
-                    for (old, new) in import.names.iter() {
-                        context
-                            .module
-                            .names
-                            .entry(old.clone())
-                            .or_insert_with(|| new.clone());
-                    }
-
-                    return Ok(None);
-                } else {
-                    rule = Some(pair.as_str().into());
-                }
+        }
 
-                None
+        for module in &modules {
+            for child in module.children.iter().filter(|child| !child.is_import()) {
+                write!(formatter, "{}", child)?;
             }
-            Rule::comment | Rule::line_comment => comment(pair),
-            Rule::property => property(context, pair)?,
-            Rule::atrule => atrule(context, pair)?,
-            Rule::selectrule => selectrule(context, pair)?,
-            _ => return Err(anyhow!(Error::from(pair))),
-        };
+        }
 
-        if let Some(child) = child {
+        Ok(())
+    }
+}
+
+/// Transforms parsed node to child of CSS module.
+pub fn child(context: &mut ParserContext, node: Node) -> Result<Option<Child>> {
+    match node {
+        Node::AtRule {
+            name,
+            prelude,
+            block,
+        } => atrule(context, name, &prelude, block),
+        Node::QualifiedRule { prelude, block } => selectrule(context, &prelude, block),
+        Node::Declaration { name, value } => property(context, name, &value),
+        Node::Comment(value) => Ok(comment(value)),
+    }
+}
+
+fn children(context: &mut ParserContext, nodes: Vec<Node>) -> Result<Children> {
+    let mut children = Vec::new();
+
+    for node in nodes {
+        if let Some(child) = child(context, node)? {
             children.push(child);
         }
     }
 
+    Ok(children)
+}
+
+pub fn atrule(
+    context: &mut ParserContext,
+    name: &str,
+    prelude: &[Token],
+    block: Option<Vec<Node>>,
+) -> Result<Option<Child>> {
+    let rule =
+        if name.eq_ignore_ascii_case("keyframes") || name.eq_ignore_ascii_case("counter-style") {
+            replace_names(context, prelude)?
+        } else if name.eq_ignore_ascii_case("import") {
+            return import(context, prelude);
+        } else if name.eq_ignore_ascii_case("charset") {
+            // bundle is always written in UTF-8, while `@charset` is only valid as the very first
+            // rule of stylesheet, so it is dropped rather than repeated for every module
+            return Ok(None);
+        } else {
+            Some(to_css(prelude)).filter(|rule| !rule.is_empty())
+        };
+
+    // rule is either terminated with semicolon, or followed by block
+    let children = match block {
+        Some(block) => {
+            let is_nested = std::mem::replace(&mut context.is_nested, true);
+            let at_rule = context.at_rule.replace(name.to_ascii_lowercase());
+            let children = children(context, block);
+            context.is_nested = is_nested;
+            context.at_rule = at_rule;
+            Some(children?)
+        }
+        None => None,
+    };
+
     Ok(Some(Child::AtRule {
-        name,
+        name: Some(name.into()),
         rule,
         children,
     }))
 }
 
-pub fn comment(pair: Pair<Rule>) -> Option<Child> {
-    Some(Child::Comment {
-        value: Some(pair.as_str().into()),
-    })
+/// Gets URL of imported module from prelude of `@import` rule, along with index of token
+/// following it.
+fn import_url<'t>(tokens: &'t [Token]) -> Option<(&'t str, usize)> {
+    match tokens.first() {
+        Some(token) if token.kind == TokenKind::String => Some((token.value.as_ref(), 1)),
+        _ => url(tokens, 0),
+    }
 }
 
-pub fn property<'t>(context: &mut ParserContext, pair: Pair<'t, Rule>) -> Result<Option<Child>> {
-    let mut name: Option<String> = None;
-    let mut value: Option<String> = None;
-
-    for pair in pair.into_inner() {
-        match pair.as_rule() {
-            Rule::identifier => {
-                name = Some(pair.as_str().into());
-            }
-            Rule::property_value => {
-                if Some("animation".into()) == name || Some("animation-name".into()) == name {
-                    value = replace_names(context, parser::animation(pair.as_str())?)?;
-                } else {
-                    value = Some(pair.as_str().trim().into());
+/// Collects paths of local modules imported by parsed module, without transforming it.
+///
+/// Should be in sync with rules [`import`] is applied to, since imported modules are required to
+/// be transformed before importing module.
+fn import_paths(nodes: &[Node], absolute_path: &Path, paths: &mut Vec<PathBuf>) {
+    for node in nodes {
+        match node {
+            Node::AtRule { name, prelude, .. } if name.eq_ignore_ascii_case("import") => {
+                if let Some((url, _)) = import_url(trim(prelude)) {
+                    if !is_external_url(url) {
+                        paths.push(normalize_path(&absolute_path.join(url)));
+                    }
                 }
             }
-            _ => return Err(anyhow!(Error::from(pair))),
+            Node::AtRule {
+                block: Some(block), ..
+            }
+            | Node::QualifiedRule { block, .. } => import_paths(block, absolute_path, paths),
+            Node::AtRule { block: None, .. } | Node::Declaration { .. } | Node::Comment(_) => {}
+        }
+    }
+}
+
+pub fn import(context: &mut ParserContext, prelude: &[Token]) -> Result<Option<Child>> {
+    let tokens = trim(prelude);
+    let mut layer = None;
+    let mut supports = None;
+    let mut media = None;
+
+    let (url, mut index) =
+        import_url(tokens).with_context(|| format!("Invalid import URL: `{}`", to_css(tokens)))?;
+
+    let skip_whitespace = |index: &mut usize| {
+        while tokens
+            .get(*index)
+            .is_some_and(|token| token.kind == TokenKind::Whitespace)
+        {
+            *index += 1;
+        }
+    };
+
+    skip_whitespace(&mut index);
+
+    if let Some(token) = tokens.get(index) {
+        if token.is_ident("layer") {
+            layer = Some(None);
+            index += 1;
+        } else if token.is_function("layer") {
+            let end = closing_paren(tokens, index);
+            let name = to_css(&tokens[index + 1..end]).trim().to_owned();
+
+            layer = Some(Some(name));
+            index = end + 1;
         }
     }
 
-    Ok(Some(Child::Property { name, value }))
+    skip_whitespace(&mut index);
+
+    if tokens
+        .get(index)
+        .is_some_and(|token| token.is_function("supports"))
+    {
+        let end = closing_paren(tokens, index);
+        let condition = to_css(&tokens[index + 1..end]);
+        let condition = condition.trim();
+
+        // condition can be a declaration (eg. `display: grid`), which should be wrapped
+        // with parentheses to become a valid condition for `@supports` rule
+        supports = Some(
+            if condition.starts_with('(')
+                || condition.starts_with("not ")
+                || condition.starts_with("selector(")
+            {
+                condition.to_owned()
+            } else {
+                format!("({})", condition)
+            },
+        );
+        index = end + 1;
+    }
+
+    if let Some(rest) = tokens.get(index..).map(to_css) {
+        if !rest.trim().is_empty() {
+            media = Some(rest.trim().to_owned());
+        }
+    }
+
+    if is_external_url(url) {
+        // leave as is for browser to load
+        return Ok(Some(Child::AtRule {
+            name: Some("import".into()),
+            rule: Some(to_css(tokens)),
+            children: None,
+        }));
+    }
+
+    let path = normalize_path(&context.absolute_path.join(url));
+    let import = context.stylesheet.load_module(&path)?;
+
+    for (old, new) in import.names.iter() {
+        context.classes.insert(old.clone());
+        context
+            .module
+            .names
+            .entry(old.clone())
+            .or_insert_with(|| new.clone());
+    }
+
+    if layer.is_none() && supports.is_none() && media.is_none() {
+        // module will be rendered to CSS bundle separately before importing module
+        context.module.imports.push(path);
+        return Ok(None);
+    }
+
+    // imported module is rendered inplace wrapped with at-rules matching import conditions
+    let mut child = import.children.clone();
+    context.module.conditional_imports.push(path);
+
+    if let Some(layer) = layer {
+        child = vec![Child::AtRule {
+            name: Some("layer".into()),
+            rule: layer,
+            children: Some(child),
+        }];
+    }
+
+    if let Some(supports) = supports {
+        child = vec![Child::AtRule {
+            name: Some("supports".into()),
+            rule: Some(supports),
+            children: Some(child),
+        }];
+    }
+
+    if let Some(media) = media {
+        child = vec![Child::AtRule {
+            name: Some("media".into()),
+            rule: Some(media),
+            children: Some(child),
+        }];
+    }
+
+    Ok(child.pop())
 }
 
-pub fn selectrule<'t>(context: &mut ParserContext, pair: Pair<'t, Rule>) -> Result<Option<Child>> {
-    let mut rule: Option<String> = None;
-    let mut children = Vec::new();
+pub fn comment(value: &str) -> Option<Child> {
+    Some(Child::Comment {
+        value: Some(close_comment(value).into_owned()),
+    })
+}
 
-    for pair in pair.into_inner() {
-        let child = match pair.as_rule() {
-            Rule::selectrule_rule => {
-                rule = replace_names(context, parser::selector(pair.as_str())?)?;
+pub fn property(context: &mut ParserContext, name: &str, value: &[Token]) -> Result<Option<Child>> {
+    let property = name.to_ascii_lowercase();
 
-                None
-            }
-            Rule::comment | Rule::line_comment => comment(pair),
-            Rule::property => property(context, pair)?,
-            Rule::atrule => atrule(context, pair)?,
-            Rule::selectrule => selectrule(context, pair)?,
-            _ => return Err(anyhow!(Error::from(pair))),
-        };
+    let value = if property == "composes" {
+        compose(context, value)?;
+        return Ok(None);
+    } else if property == "animation" || property == "animation-name" {
+        replace_animation_names(context, &property, value)?
+    } else if is_grid_property(&property) {
+        replace_grid_names(context, value)?
+    } else {
+        let has_counter_function = value
+            .iter()
+            .any(|token| token.is_function("counter") || token.is_function("counters"));
+        let mut value = replace_urls(context, value)?;
 
-        if let Some(child) = child {
-            children.push(child);
+        if is_counter_property(context, &property) || has_counter_function {
+            value = replace_counter_names(context, &property, &tokenize(&value))?;
+        }
+
+        value
+    };
+
+    Ok(Some(Child::Property {
+        name: Some(name.into()),
+        value: Some(value),
+    }))
+}
+
+pub fn compose(context: &mut ParserContext, value: &[Token]) -> Result<()> {
+    let rule_classes = context.rule_classes.clone().context(
+        "Composition is only allowed in top-level rules with single local class selectors \
+            (eg. `.foo` or `.foo, .bar`)",
+    )?;
+
+    let tokens: Vec<&Token> = value
+        .iter()
+        .filter(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment))
+        .collect();
+    let (names, from) = match tokens.iter().position(|token| token.is_ident("from")) {
+        Some(index) => (&tokens[..index], Some(&tokens[index + 1..])),
+        None => (&tokens[..], None),
+    };
+
+    let mut is_global = false;
+    let mut module_path = None;
+
+    match from {
+        None => {}
+        Some([token]) if token.is_ident("global") => is_global = true,
+        Some([token]) if token.kind == TokenKind::String => {
+            module_path = Some(normalize_path(
+                &context.absolute_path.join(token.value.as_ref()),
+            ));
         }
+        _ => return Err(anyhow!("Invalid composition: `{}`", to_css(value))),
+    }
+
+    if names.is_empty() || names.iter().any(|token| token.kind != TokenKind::Ident) {
+        return Err(anyhow!("Invalid composition: `{}`", to_css(value)));
+    }
+
+    let names = names.iter().map(|token| token.value.to_string());
+
+    let mut compositions = Vec::new();
+
+    for name in names {
+        compositions.push(if is_global {
+            Composition::Global(name)
+        } else if let Some(module_path) = &module_path {
+            Composition::External {
+                module_path: module_path.clone(),
+                name,
+            }
+        } else {
+            context.composed_classes.push(name.clone());
+            Composition::Local(context.add_name(name)?)
+        });
+    }
+
+    for class in rule_classes {
+        context
+            .module
+            .compositions
+            .entry(class)
+            .or_default()
+            .extend(compositions.iter().cloned());
     }
 
-    Ok(Some(Child::SelectRule { rule, children }))
+    Ok(())
 }
 
-pub fn replace_names(context: &mut ParserContext, pairs: Pairs<Rule>) -> Result<Option<String>> {
+/// Replaces keyframes names in values of `animation` and `animation-name` properties.
+///
+/// Value may contain several comma-separated animations. In `animation` shorthand, identifier is
+/// treated as keyframes name only if it is not a keyword of other animation property, which was
+/// not set yet for the same animation (eg. in `ease ease` first identifier is a timing function,
+/// and second one is a keyframes name).
+pub fn replace_animation_names(
+    context: &mut ParserContext,
+    property: &str,
+    tokens: &[Token],
+) -> Result<String> {
+    const KEYWORDS: &[&[&str]] = &[
+        // timing function
+        &[
+            "ease",
+            "ease-in",
+            "ease-in-out",
+            "ease-out",
+            "linear",
+            "step-end",
+            "step-start",
+        ],
+        // iteration count
+        &["infinite"],
+        // direction
+        &["alternate", "alternate-reverse", "normal", "reverse"],
+        // fill mode
+        &["backwards", "both", "forwards", "none"],
+        // play state
+        &["paused", "running"],
+    ];
+    const NAME_KEYWORDS: &[&str] = &[
+        "inherit",
+        "initial",
+        "none",
+        "revert",
+        "revert-layer",
+        "unset",
+    ];
+
     let mut result = String::new();
 
-    for pair in pairs {
-        match pair.as_rule() {
-            Rule::identifier => {
-                result.push_str(&context.add_name(pair.as_str().trim().into()));
+    // whether keywords of particular animation property were already set for current animation
+    let mut is_set = [false; KEYWORDS.len()];
+    let mut index = 0;
+
+    while let Some(token) = tokens.get(index) {
+        if let Some((mode, name, next)) = scoped_name(tokens, index) {
+            match mode {
+                Mode::Global => result.push_str(name.source),
+                Mode::Local => result.push_str(&context.add_ident(&name.value)?),
+            }
+
+            index = next;
+            continue;
+        }
+
+        match token.kind {
+            TokenKind::Ident => {
+                let lowercase_name = token.value.to_ascii_lowercase();
+
+                let keywords = KEYWORDS.iter().enumerate().find(|(index, keywords)| {
+                    property == "animation"
+                        && !is_set[*index]
+                        && keywords.contains(&lowercase_name.as_str())
+                });
+
+                if let Some((index, _)) = keywords {
+                    is_set[index] = true;
+                    result.push_str(token.source);
+                } else if context.mode == Mode::Local
+                    && !NAME_KEYWORDS.contains(&lowercase_name.as_str())
+                {
+                    result.push_str(&context.add_ident(&token.value)?);
+                } else {
+                    result.push_str(token.source);
+                }
             }
-            Rule::selector_class => {
-                result.push_str(&format!(
-                    ".{}",
-                    &context.add_name(pair.as_str()[1..].trim().into())
-                ));
+            TokenKind::Function => {
+                // skip function arguments (eg. `steps(4, end)`)
+                let end = closing_paren(tokens, index);
+                result.push_str(&to_css(&tokens[index..tokens.len().min(end + 1)]));
+                index = end;
             }
-            _ => {
-                result.push_str(pair.as_str());
+            TokenKind::Comma => {
+                is_set = [false; KEYWORDS.len()];
+                result.push_str(token.source);
             }
+            _ => result.push_str(token.source),
         }
+
+        index += 1;
     }
 
-    result = result.trim().into();
+    Ok(result)
+}
 
-    if result.is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(result))
-    }
+fn is_grid_property(name: &str) -> bool {
+    matches!(
+        name,
+        "grid"
+            | "grid-template"
+            | "grid-template-areas"
+            | "grid-template-columns"
+            | "grid-template-rows"
+            | "grid-area"
+            | "grid-row"
+            | "grid-row-start"
+            | "grid-row-end"
+            | "grid-column"
+            | "grid-column-start"
+            | "grid-column-end"
+    )
 }
 
-#[cfg(test)]
-#[allow(clippy::unwrap_used)]
-mod tests {
-    use super::*;
+/// Collects names of grid areas defined in parsed module, either in strings of
+/// `grid-template-areas` (eg. `"head head"`), or implicitly with pairs of lines with "-start" and
+/// "-end" suffixes (eg. `[main-start] 1fr [main-end]`).
+fn grid_areas(nodes: &[Node]) -> HashSet<String> {
+    fn collect(nodes: &[Node], areas: &mut HashSet<String>, lines: &mut HashSet<String>) {
+        for node in nodes {
+            match node {
+                Node::Declaration { name, value }
+                    if is_grid_property(&name.to_ascii_lowercase()) =>
+                {
+                    let mut is_line = false;
+
+                    for token in value {
+                        match token.kind {
+                            TokenKind::OpenSquare => is_line = true,
+                            TokenKind::CloseSquare => is_line = false,
+                            TokenKind::Ident if is_line => {
+                                lines.insert(token.value.to_string());
+                            }
+                            TokenKind::String => areas.extend(
+                                token
+                                    .value
+                                    .split(|c| !is_ident_char(c))
+                                    .filter(|name| !name.is_empty())
+                                    .map(String::from),
+                            ),
+                            _ => {}
+                        }
+                    }
+                }
+                Node::AtRule {
+                    block: Some(block), ..
+                }
+                | Node::QualifiedRule { block, .. } => collect(block, areas, lines),
+                _ => {}
+            }
+        }
+    }
 
-    #[test]
-    fn parses_empty_stylesheet() {
-        assert_eq!(
-            Stylesheet::default(),
-            Stylesheet {
-                names_count: 0,
-                modules: BTreeMap::new(),
+    let mut areas = HashSet::new();
+    let mut lines = HashSet::new();
+    collect(nodes, &mut areas, &mut lines);
+
+    for line in &lines {
+        if let Some(area) = line.strip_suffix("-start") {
+            if lines.contains(&format!("{}-end", area)) {
+                areas.insert(area.to_owned());
             }
-        )
+        }
     }
 
-    #[test]
+    areas
+}
+
+/// Splits grid name into name of area and "-start" / "-end" suffix, if it is a name of implicit
+/// line of area defined in module (eg. `foo-start` for area `foo`), so suffix is kept
+/// untransformed for line name to still match transformed area name.
+fn split_grid_name<'n>(areas: &HashSet<String>, name: &'n str) -> (&'n str, &'static str) {
+    if areas.contains(name) {
+        return (name, "");
+    }
+
+    ["-start", "-end"]
+        .iter()
+        .find_map(|suffix| {
+            name.strip_suffix(suffix)
+                .filter(|area| areas.contains(*area))
+                .map(|area| (area, *suffix))
+        })
+        .unwrap_or((name, ""))
+}
+
+/// Replaces names of grid areas and lines.
+pub fn replace_grid_names(context: &mut ParserContext, tokens: &[Token]) -> Result<String> {
+    const KEYWORDS: &[&str] = &[
+        "auto",
+        "auto-fill",
+        "auto-fit",
+        "auto-flow",
+        "column",
+        "dense",
+        "inherit",
+        "initial",
+        "masonry",
+        "max-content",
+        "min-content",
+        "none",
+        "revert",
+        "revert-layer",
+        "row",
+        "span",
+        "subgrid",
+        "unset",
+    ];
+
+    let mut result = String::new();
+
+    // names of functions, which current token is nested in
+    let mut functions: Vec<String> = Vec::new();
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Function => functions.push(token.value.to_ascii_lowercase()),
+            TokenKind::OpenParen => functions.push(String::new()),
+            TokenKind::CloseParen => {
+                functions.pop();
+            }
+            _ => {}
+        }
+
+        // names can be nested in `repeat()` only (eg. `repeat(2, [col] 1fr)`)
+        let is_name =
+            context.mode == Mode::Local && functions.iter().all(|function| function == "repeat");
+
+        match token.kind {
+            TokenKind::String if is_name => {
+                result.push_str(&replace_grid_area_names(context, token.source)?);
+            }
+            TokenKind::Ident
+                if is_name
+                    && !KEYWORDS.contains(&token.value.to_ascii_lowercase().as_str())
+                    && !token.value.starts_with("--") =>
+            {
+                let (name, suffix) = split_grid_name(&context.grid_areas, &token.value);
+
+                result.push_str(&context.add_ident(name)?);
+                result.push_str(suffix);
+            }
+            _ => result.push_str(token.source),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Replaces names of grid areas in string of `grid-template-areas` (eg. `"head head"`).
+fn replace_grid_area_names(context: &mut ParserContext, string: &str) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = string;
+
+    while let Some(c) = rest.chars().next() {
+        let is_name = is_ident_char(c);
+        let end = rest
+            .find(|c| is_ident_char(c) != is_name)
+            .unwrap_or(rest.len());
+        let (part, tail) = rest.split_at(end);
+
+        if is_name {
+            let (name, suffix) = split_grid_name(&context.grid_areas, part);
+
+            result.push_str(&context.add_name(name.into())?);
+            result.push_str(suffix);
+        } else {
+            result.push_str(part);
+        }
+
+        rest = tail;
+    }
+
+    Ok(result)
+}
+
+fn is_counter_property(context: &ParserContext, name: &str) -> bool {
+    matches!(
+        name,
+        "counter-increment" | "counter-reset" | "counter-set" | "list-style" | "list-style-type"
+    ) || is_counter_style_descriptor(context, name)
+}
+
+/// Checks whether declaration is a `@counter-style` descriptor, which references other counter
+/// styles (eg. `system: extends foo`). Such declarations are only valid directly inside
+/// `@counter-style` block, while properties with the same names elsewhere are left untouched.
+fn is_counter_style_descriptor(context: &ParserContext, name: &str) -> bool {
+    matches!(name, "system" | "fallback") && context.at_rule.as_deref() == Some("counter-style")
+}
+
+/// Replaces names of counters and counter styles.
+///
+/// Names are replaced in values of counter properties (eg. `counter-reset: foo`), list style
+/// properties (eg. `list-style: foo`), `@counter-style` descriptors (eg. `system: extends foo`),
+/// and in arguments of `counter()` / `counters()` functions.
+pub fn replace_counter_names(
+    context: &mut ParserContext,
+    property: &str,
+    tokens: &[Token],
+) -> Result<String> {
+    const COUNTER_KEYWORDS: &[&str] = &[
+        "inherit",
+        "initial",
+        "list-item",
+        "none",
+        "revert",
+        "revert-layer",
+        "unset",
+    ];
+    const COUNTER_STYLE_KEYWORDS: &[&str] = &[
+        // keywords
+        "additive",
+        "alphabetic",
+        "cyclic",
+        "extends",
+        "fixed",
+        "inherit",
+        "initial",
+        "inside",
+        "none",
+        "numeric",
+        "outside",
+        "revert",
+        "revert-layer",
+        "symbolic",
+        "unset",
+        // predefined counter styles
+        "arabic-indic",
+        "armenian",
+        "bengali",
+        "cambodian",
+        "circle",
+        "cjk-decimal",
+        "cjk-earthly-branch",
+        "cjk-heavenly-stem",
+        "cjk-ideographic",
+        "decimal",
+        "decimal-leading-zero",
+        "devanagari",
+        "disc",
+        "disclosure-closed",
+        "disclosure-open",
+        "ethiopic-numeric",
+        "georgian",
+        "gujarati",
+        "gurmukhi",
+        "hebrew",
+        "hiragana",
+        "hiragana-iroha",
+        "japanese-formal",
+        "japanese-informal",
+        "kannada",
+        "katakana",
+        "katakana-iroha",
+        "khmer",
+        "korean-hangul-formal",
+        "korean-hanja-formal",
+        "korean-hanja-informal",
+        "lao",
+        "lower-alpha",
+        "lower-armenian",
+        "lower-greek",
+        "lower-latin",
+        "lower-roman",
+        "malayalam",
+        "mongolian",
+        "myanmar",
+        "oriya",
+        "persian",
+        "simp-chinese-formal",
+        "simp-chinese-informal",
+        "square",
+        "tamil",
+        "telugu",
+        "thai",
+        "tibetan",
+        "trad-chinese-formal",
+        "trad-chinese-informal",
+        "upper-alpha",
+        "upper-armenian",
+        "upper-latin",
+        "upper-roman",
+    ];
+
+    let mut result = String::new();
+
+    // names of functions, which current name is nested in, with indexes of current arguments
+    let mut functions: Vec<(String, usize)> = Vec::new();
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Function => {
+                functions.push((token.value.to_ascii_lowercase(), 0));
+            }
+            TokenKind::OpenParen => functions.push((String::new(), 0)),
+            TokenKind::CloseParen => {
+                functions.pop();
+            }
+            TokenKind::Comma => {
+                if let Some((_, argument)) = functions.last_mut() {
+                    *argument += 1;
+                }
+            }
+            TokenKind::Ident if context.mode == Mode::Local => {
+                let keywords = match functions.last() {
+                    None => match property {
+                        "counter-increment" | "counter-reset" | "counter-set" => {
+                            Some(COUNTER_KEYWORDS)
+                        }
+                        "list-style" | "list-style-type" => Some(COUNTER_STYLE_KEYWORDS),
+                        _ if is_counter_style_descriptor(context, property) => {
+                            Some(COUNTER_STYLE_KEYWORDS)
+                        }
+                        _ => None,
+                    },
+                    Some((function, argument)) => match (function.as_str(), argument) {
+                        ("counter" | "counters" | "reversed", 0) => Some(COUNTER_KEYWORDS),
+                        ("counter", 1) | ("counters", 2) => Some(COUNTER_STYLE_KEYWORDS),
+                        _ => None,
+                    },
+                };
+
+                match keywords {
+                    Some(keywords)
+                        if !keywords.contains(&token.value.to_ascii_lowercase().as_str()) =>
+                    {
+                        result.push_str(&context.add_ident(&token.value)?);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        result.push_str(token.source);
+    }
+
+    Ok(result)
+}
+
+pub fn replace_urls(context: &mut ParserContext, tokens: &[Token]) -> Result<String> {
+    let mut result = String::new();
+    let mut index = 0;
+
+    while let Some(token) = tokens.get(index) {
+        if let Some((url, next)) = url(tokens, index) {
+            result.push_str(&format!("url({})", serialize_string(&asset(context, url)?)));
+            index = next;
+        } else {
+            result.push_str(token.source);
+            index += 1;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Gets URL of `url()` at given position of tokens (eg. `url(a.png)` or `url("a.png")`), along
+/// with position of the next token after it.
+fn url<'t>(tokens: &'t [Token], index: usize) -> Option<(&'t str, usize)> {
+    let token = tokens.get(index)?;
+
+    if token.kind == TokenKind::Url {
+        return Some((&token.value, index + 1));
+    }
+
+    if !token.is_function("url") {
+        return None;
+    }
+
+    let end = closing_paren(tokens, index);
+
+    match trim(&tokens[index + 1..end]) {
+        [string] if string.kind == TokenKind::String => Some((&string.value, end + 1)),
+        _ => None,
+    }
+}
+
+/// Gets position of parenthesis closing function or parenthesis at given position of tokens, or
+/// length of tokens if it is not closed.
+fn closing_paren(tokens: &[Token], index: usize) -> usize {
+    let mut depth = 0;
+
+    for (index, token) in tokens.iter().enumerate().skip(index) {
+        match token.kind {
+            TokenKind::Function | TokenKind::OpenParen => depth += 1,
+            TokenKind::CloseParen => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return index;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    tokens.len()
+}
+
+/// Gets name wrapped with `:global()` or `:local()` at given position of tokens (eg.
+/// `:global(foo)`), along with its scoping mode and position of the next token after it.
+fn scoped_name<'t, 'i>(
+    tokens: &'t [Token<'i>],
+    index: usize,
+) -> Option<(Mode, &'t Token<'i>, usize)> {
+    if tokens.get(index)?.kind != TokenKind::Colon {
+        return None;
+    }
+
+    let function = tokens.get(index + 1)?;
+    let mode = if function.is_function("global") {
+        Mode::Global
+    } else if function.is_function("local") {
+        Mode::Local
+    } else {
+        return None;
+    };
+
+    let end = closing_paren(tokens, index + 1);
+
+    match trim(&tokens[index + 2..end]) {
+        [name] if name.kind == TokenKind::Ident => Some((mode, name, end + 1)),
+        _ => None,
+    }
+}
+
+/// Checks whether URL should be left as is for browser to resolve, rather than resolved to a file
+/// relative to module file (eg. absolute URLs with or without scheme, and fragment-only URLs).
+fn is_external_url(url: &str) -> bool {
+    url.is_empty()
+        || url.starts_with('/')
+        || url.starts_with('#')
+        || url
+            .split_once(':')
+            .map(|(scheme, _)| {
+                scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            })
+            .unwrap_or_default()
+}
+
+/// Resolves asset file referenced with `url()` relative to module file.
+///
+/// Returns URL of the asset in output CSS bundle: either data URI if asset is inlined, or
+/// relative path to asset file copied next to CSS bundle.
+fn asset(context: &mut ParserContext, url: &str) -> Result<String> {
+    if is_external_url(url) {
+        return Ok(url.to_owned());
+    }
+
+    // keep query and fragment (eg. `font.eot?#iefix` or `icons.svg#icon`)
+    let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+    let path = normalize_path(&context.absolute_path.join(percent_decode(path)));
+
+    let mut content = Vec::new();
+    File::open(&path)
+        .and_then(|mut file| file.read_to_end(&mut content))
+        .with_context(|| format!("Failed to read asset file: {:?}", path))?;
+
+    if let Some(limit) = context.stylesheet.inline_assets_limit {
+        if suffix.is_empty() && content.len() as u64 <= limit {
+            context.module.assets.insert(path.clone(), None);
+            return Ok(format!(
+                "data:{};base64,{}",
+                mime_type(&path),
+                base64::engine::general_purpose::STANDARD.encode(&content)
+            ));
+        }
+    }
+
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .context("Invalid asset file path")?;
+    let file_name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}.{:016x}.{}", stem, hash(&content), extension),
+        None => format!("{}.{:016x}", stem, hash(&content)),
+    };
+
+    context
+        .module
+        .assets
+        .insert(path.clone(), Some(file_name.clone()));
+    context.stylesheet.assets.insert(path, file_name.clone());
+
+    Ok(file_name + suffix)
+}
+
+pub fn selectrule(
+    context: &mut ParserContext,
+    prelude: &[Token],
+    block: Vec<Node>,
+) -> Result<Option<Child>> {
+    let parent_rule_classes = context.rule_classes.take();
+
+    let rule = replace_selector_names(context, prelude, context.mode)?;
+    let rule = Some(rule.trim().to_owned()).filter(|rule| !rule.is_empty());
+    // classes can only be composed into rules which are not nested in other rules (eg. in
+    // `@media`), since composition applies regardless of where class is used
+    context.rule_classes = match context.is_nested {
+        false => rule_classes(context, prelude),
+        true => None,
+    };
+
+    let is_nested = std::mem::replace(&mut context.is_nested, true);
+    let at_rule = context.at_rule.take();
+    let children = children(context, block);
+    context.is_nested = is_nested;
+    context.at_rule = at_rule;
+    context.rule_classes = parent_rule_classes;
+
+    Ok(Some(Child::SelectRule {
+        rule,
+        children: children?,
+    }))
+}
+
+fn rule_classes(context: &ParserContext, tokens: &[Token]) -> Option<Vec<String>> {
+    if context.mode != Mode::Local {
+        return None;
+    }
+
+    tokens
+        .split(|token| token.kind == TokenKind::Comma)
+        .map(|selector| {
+            let selector: Vec<&Token> = trim(selector)
+                .iter()
+                .filter(|token| token.kind != TokenKind::Comment)
+                .collect();
+
+            match selector[..] {
+                [dot, name]
+                    if dot.kind == TokenKind::Delim('.') && name.kind == TokenKind::Ident =>
+                {
+                    context.module.names.get(name.value.as_ref()).cloned()
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Replaces names in selector.
+///
+/// Scoping mode can be switched with `:global` / `:local` until the end of selector (eg.
+/// `:global .foo .bar`), or for their arguments only (eg. `:global(.foo) .bar`).
+pub fn replace_selector_names(
+    context: &mut ParserContext,
+    tokens: &[Token],
+    mode: Mode,
+) -> Result<String> {
+    let mut result = String::new();
+    let default_mode = mode;
+    let mut mode = mode;
+
+    // modes before functions and parentheses, which current token is nested in
+    let mut modes = Vec::new();
+    let mut index = 0;
+
+    while let Some(token) = tokens.get(index) {
+        index += 1;
+
+        let next = tokens.get(index);
+        let scope_mode = next.and_then(|next| {
+            if next.is_ident("global") || next.is_function("global") {
+                Some(Mode::Global)
+            } else if next.is_ident("local") || next.is_function("local") {
+                Some(Mode::Local)
+            } else {
+                None
+            }
+        });
+
+        match (token.kind, next, scope_mode) {
+            (TokenKind::Delim('.'), Some(next), _)
+                if mode == Mode::Local && next.kind == TokenKind::Ident =>
+            {
+                result.push('.');
+                result.push_str(&context.add_ident(&next.value)?);
+                context.classes.insert(next.value.to_string());
+                index += 1;
+            }
+            (TokenKind::Colon, Some(next), Some(scope_mode)) if next.kind == TokenKind::Ident => {
+                mode = scope_mode;
+                index += 1;
+
+                while tokens
+                    .get(index)
+                    .is_some_and(|token| token.kind == TokenKind::Whitespace)
+                {
+                    index += 1;
+                }
+            }
+            (TokenKind::Colon, Some(_), Some(scope_mode)) => {
+                let end = closing_paren(tokens, index);
+                let args = replace_selector_names(context, &tokens[index + 1..end], scope_mode)?;
+
+                result.push_str(args.trim());
+                index = end + 1;
+            }
+            (TokenKind::Comma, _, _) => {
+                // mode switched with `:global` / `:local` is reset for each selector in the list
+                mode = modes.last().copied().unwrap_or(default_mode);
+                result.push_str(token.source);
+            }
+            (TokenKind::Function | TokenKind::OpenParen, _, _) => {
+                modes.push(mode);
+                result.push_str(token.source);
+            }
+            (TokenKind::CloseParen, _, _) => {
+                mode = modes.pop().unwrap_or(mode);
+                result.push_str(token.source);
+            }
+            _ => result.push_str(token.source),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Replaces name in prelude of `@keyframes` or `@counter-style` rule.
+pub fn replace_names(context: &mut ParserContext, tokens: &[Token]) -> Result<Option<String>> {
+    let mut result = String::new();
+    let mut index = 0;
+
+    while let Some(token) = tokens.get(index) {
+        if let Some((mode, name, next)) = scoped_name(tokens, index) {
+            match mode {
+                Mode::Global => result.push_str(name.source),
+                Mode::Local => result.push_str(&context.add_ident(&name.value)?),
+            }
+
+            index = next;
+            continue;
+        }
+
+        if token.kind == TokenKind::Ident && context.mode == Mode::Local {
+            result.push_str(&context.add_ident(&token.value)?);
+        } else {
+            result.push_str(token.source);
+        }
+
+        index += 1;
+    }
+
+    Ok(Some(result).filter(|result| !result.is_empty()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::utils::write_test_files;
+
+    /// Creates stylesheet with readable global names (eg. `ast__foo__0`).
+    fn stylesheet() -> Stylesheet {
+        Stylesheet {
+            naming: "[name]__[local]__[index]".into(),
+            ..Stylesheet::default()
+        }
+    }
+
+    fn exports(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(local, global)| (local.to_string(), global.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_empty_stylesheet() {
+        assert_eq!(
+            Stylesheet::default(),
+            Stylesheet {
+                names_count: 0,
+                modules: BTreeMap::new(),
+                entries: BTreeSet::new(),
+                import_chain: Vec::new(),
+                default_mode: Mode::Local,
+                module_modes: BTreeMap::new(),
+                naming: Naming::default(),
+                naming_salt: String::new(),
+                base_dir: PathBuf::new(),
+                assets: BTreeMap::new(),
+                inline_assets_limit: None,
+                cache: None,
+                content_hashes: BTreeMap::new(),
+            }
+        )
+    }
+
+    #[test]
     fn parses_empty_select_rule() {
         assert_eq!(
-            Stylesheet::default().add_test_module(".foobar {}").unwrap(),
+            stylesheet().add_test_module(".foobar {}").unwrap(),
             &Module {
                 children: vec![Child::SelectRule {
                     children: Vec::new(),
@@ -401,7 +1921,7 @@ mod tests {
     #[test]
     fn parses_select_rule_with_property() {
         assert_eq!(
-            Stylesheet::default()
+            stylesheet()
                 .add_test_module(".foobar { color: red; }")
                 .unwrap(),
             &Module {
@@ -423,13 +1943,11 @@ mod tests {
     #[test]
     fn parses_empty_at_rule() {
         assert_eq!(
-            Stylesheet::default()
-                .add_test_module("@keyframes foobar;")
-                .unwrap(),
+            stylesheet().add_test_module("@keyframes foobar;").unwrap(),
             &Module {
                 children: vec![Child::AtRule {
                     name: Some("keyframes".into()),
-                    children: Vec::new(),
+                    children: None,
                     rule: Some("ast__foobar__0".into()),
                 }],
                 names: vec![("foobar".into(), "ast__foobar__0".into())]
@@ -442,7 +1960,7 @@ mod tests {
 
     #[test]
     fn format_empty_module() {
-        let mut stylesheet = Stylesheet::default();
+        let mut stylesheet = stylesheet();
         let module = stylesheet.add_test_module("").unwrap();
 
         assert_eq!(format!("{}", module), String::new());
@@ -450,7 +1968,7 @@ mod tests {
 
     #[test]
     fn format_select_rule() {
-        let mut stylesheet = Stylesheet::default();
+        let mut stylesheet = stylesheet();
         let module = stylesheet
             .add_test_module("p.foobar  {  color :  #fff ;  }")
             .unwrap();
@@ -463,7 +1981,7 @@ mod tests {
 
     #[test]
     fn format_at_rule() {
-        let mut stylesheet = Stylesheet::default();
+        let mut stylesheet = stylesheet();
         let module = stylesheet
             .add_test_module("@keyframes animation {0% { top: 0;} 100% {top: 100px;}}")
             .unwrap();
@@ -474,13 +1992,893 @@ mod tests {
         );
     }
 
+    #[test]
+    fn format_global_selector() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(":global(.foo) .bar, :global(.baz:not(.qux)) {}")
+            .unwrap();
+
+        assert_eq!(
+            module.children,
+            vec![Child::SelectRule {
+                rule: Some(".foo .ast__bar__0, .baz:not(.qux)".into()),
+                children: Vec::new(),
+            }]
+        );
+        assert_eq!(
+            module.names,
+            vec![("bar".into(), "ast__bar__0".into())]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn format_global_selector_switch() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(".a :global .b .c:hover, .d :global.e :local .f {}")
+            .unwrap();
+
+        assert_eq!(
+            module.children,
+            vec![Child::SelectRule {
+                rule: Some(".ast__a__0 .b .c:hover, .ast__d__1 .e .ast__f__2".into()),
+                children: Vec::new(),
+            }]
+        );
+    }
+
+    #[test]
+    fn format_global_keyframes() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                "@keyframes :global(spin) {} @keyframes :local(fade) {} \
+                    .a { animation: :global(spin) 1s; } .b { animation: fade 1s; }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            &format!("{}", module),
+            "@keyframes spin { }\n@keyframes ast__fade__0 { }\n\
+                .ast__a__1 { animation: spin 1s; }\n\
+                .ast__b__2 { animation: ast__fade__0 1s; }\n"
+        );
+    }
+
+    #[test]
+    fn format_global_mode() {
+        let mut stylesheet = Stylesheet {
+            default_mode: Mode::Global,
+            ..stylesheet()
+        };
+        let module = stylesheet
+            .add_test_module(
+                ".a :local(.b), .c {} @keyframes d {} .e { animation: d 1s; } \
+                    :local .f { animation: :local(g) 1s; }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            &format!("{}", module),
+            "@keyframes d { }\n.e { animation: d 1s; }\n\
+                .ast__f__1 { animation: ast__g__2 1s; }\n"
+        );
+        assert_eq!(
+            module.names,
+            vec![
+                ("b".into(), "ast__b__0".into()),
+                ("f".into(), "ast__f__1".into()),
+                ("g".into(), "ast__g__2".into()),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn format_naming() {
+        let input = ".a { animation: b 1s; } .c:hover .a {}";
+        let content_hash = format!("{:016x}", hash(input.as_bytes()));
+        let mut stylesheet = Stylesheet {
+            naming: "[local]-[contenthash:6]".into(),
+            ..Stylesheet::default()
+        };
+        let module = stylesheet.add_test_module(input).unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            format!(".a-{0} {{ animation: b-{0} 1s; }}\n", &content_hash[..6])
+        );
+
+        let mut stylesheet = Stylesheet {
+            naming: Naming::custom(|path, local, _| {
+                format!("{}_{}", path.extension().unwrap().to_str().unwrap(), local)
+            }),
+            ..Stylesheet::default()
+        };
+        let module = stylesheet.add_test_module(input).unwrap();
+
+        assert_eq!(format!("{}", module), ".rs_a { animation: rs_b 1s; }\n");
+    }
+
+    #[test]
+    fn format_stable_names() {
+        let dir = write_test_files(
+            "format_stable_names",
+            &[
+                ("a.css", ".a { color: red; }"),
+                ("b.css", ".b { color: red; }"),
+                ("c/b.css", ".b { color: red; }"),
+            ],
+        );
+        let name = |files: &[&str], salt: &str, file: &str| {
+            let mut stylesheet = Stylesheet {
+                naming_salt: salt.into(),
+                base_dir: dir.clone(),
+                ..Stylesheet::default()
+            };
+
+            for file in files {
+                stylesheet.add_module(&dir.join(file)).unwrap();
+            }
+
+            stylesheet.modules[&dir.join(file)]
+                .names
+                .values()
+                .next()
+                .unwrap()
+                .clone()
+        };
+        let hash = format!("{:016x}", hash(b"b.css\0b\0"));
+
+        // names only depend on module path relative to base directory and local name
+        assert_eq!(
+            name(&["b.css"], "", "b.css"),
+            format!("b__b__{}", &hash[..8])
+        );
+        assert_eq!(
+            name(&["a.css", "b.css"], "", "b.css"),
+            name(&["b.css"], "", "b.css")
+        );
+        assert_ne!(
+            name(&["b.css", "c/b.css"], "", "b.css"),
+            name(&["b.css", "c/b.css"], "", "c/b.css")
+        );
+        assert_ne!(
+            name(&["b.css"], "salt", "b.css"),
+            name(&["b.css"], "", "b.css")
+        );
+    }
+
+    #[test]
+    fn format_composes() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(".a { composes: b c; color: red; } .b {} .c {}")
+            .unwrap();
+
+        assert_eq!(&format!("{}", module), ".ast__a__0 { color: red; }\n");
+    }
+
+    #[test]
+    fn exports_composes_local() {
+        let mut stylesheet = stylesheet();
+        stylesheet
+            .add_test_module(".a, .b { composes: c; } .c { composes: d; } .d {}")
+            .unwrap();
+        let module = stylesheet.modules.values().next().unwrap();
+
+        assert_eq!(
+            stylesheet.exports(module).unwrap(),
+            exports(&[
+                ("a", "ast__a__0 ast__c__2 ast__d__3"),
+                ("b", "ast__b__1 ast__c__2 ast__d__3"),
+                ("c", "ast__c__2 ast__d__3"),
+                ("d", "ast__d__3"),
+            ])
+        );
+    }
+
+    #[test]
+    fn exports_composes_cyclic() {
+        let mut stylesheet = stylesheet();
+        stylesheet
+            .add_test_module(".a { composes: b; } .b { composes: a; }")
+            .unwrap();
+        let module = stylesheet.modules.values().next().unwrap();
+
+        assert_eq!(
+            stylesheet.exports(module).unwrap(),
+            exports(&[("a", "ast__a__0 ast__b__1"), ("b", "ast__b__1 ast__a__0"),])
+        );
+    }
+
+    #[test]
+    fn exports_composes_global() {
+        let mut stylesheet = stylesheet();
+        stylesheet
+            .add_test_module(".a { composes: b c from global; composes: d from global; }")
+            .unwrap();
+        let module = stylesheet.modules.values().next().unwrap();
+
+        assert_eq!(
+            stylesheet.exports(module).unwrap(),
+            exports(&[("a", "ast__a__0 b c d")])
+        );
+    }
+
+    #[test]
+    fn exports_composes_external() {
+        let dir = write_test_files(
+            "exports_composes_external",
+            &[
+                ("a.css", ".a { composes: b from './b.css'; }"),
+                ("b.css", ".b { composes: c from \"../c/c.css\"; }"),
+                ("../c/c.css", ".c { color: red; }"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        stylesheet.add_module(&dir.join("a.css")).unwrap();
+
+        assert_eq!(stylesheet.modules.len(), 3);
+        assert_eq!(
+            stylesheet
+                .exports(&stylesheet.modules[&dir.join("a.css")])
+                .unwrap(),
+            exports(&[("a", "a__a__0 b__b__1 c__c__2")])
+        );
+    }
+
+    #[test]
+    fn error_is_composes_unknown_external_name() {
+        let dir = write_test_files(
+            "error_is_composes_unknown_external_name",
+            &[
+                ("a.css", ".a { composes: x from './b.css'; }"),
+                ("b.css", ".b {}"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        stylesheet.add_module(&dir.join("a.css")).unwrap();
+
+        assert!(stylesheet
+            .exports(&stylesheet.modules[&dir.join("a.css")])
+            .is_err());
+    }
+
+    #[test]
+    fn error_is_composes_undefined_local() {
+        assert!(stylesheet().add_test_module(".a { composes: b; }").is_err());
+        assert!(stylesheet()
+            .add_test_module(".a { composes: b; } :global(.b) {}")
+            .is_err());
+        assert!(stylesheet()
+            .add_test_module(".a { composes: b; } .b {}")
+            .is_ok());
+    }
+
+    #[test]
+    fn error_is_composes_in_nested_rule() {
+        assert!(stylesheet()
+            .add_test_module("@media screen { .a { composes: b; } } .b {}")
+            .is_err());
+        assert!(stylesheet()
+            .add_test_module(".b {} .a { .c { composes: b; } }")
+            .is_err());
+        assert!(stylesheet()
+            .add_test_module(".b {} @supports (display: grid) { .a { composes: b; } }")
+            .is_err());
+    }
+
+    #[test]
+    fn error_is_composes_in_complex_selector() {
+        assert!(stylesheet()
+            .add_test_module(".a .b { composes: c; }")
+            .is_err());
+        assert!(stylesheet()
+            .add_test_module(".a.b { composes: c; }")
+            .is_err());
+        assert!(stylesheet()
+            .add_test_module("div { composes: c; }")
+            .is_err());
+        assert!(stylesheet()
+            .add_test_module(":global(.a) { composes: c; }")
+            .is_err());
+    }
+
+    #[test]
+    fn format_url() {
+        let dir = write_test_files(
+            "format_url",
+            &[
+                (
+                    "a.css",
+                    ".a { background: url(./img/bg.png) no-repeat, url('../b/icons.svg#icon'); } \
+                        @font-face { src: url(\"font.woff2?v=1\") format(\"woff2\"); }",
+                ),
+                ("img/bg.png", "png"),
+                ("../b/icons.svg", "svg"),
+                ("font.woff2", "woff2"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        let module = stylesheet.add_module(&dir.join("a.css")).unwrap();
+        let bg_name = format!("bg.{:016x}.png", hash(b"png"));
+        let icons_name = format!("icons.{:016x}.svg", hash(b"svg"));
+        let font_name = format!("font.{:016x}.woff2", hash(b"woff2"));
+
+        assert_eq!(
+            format!("{}", module),
+            format!(
+                ".a__a__0 {{ background: url(\"{}\") no-repeat, url(\"{}#icon\"); }}\n\
+                    @font-face {{ src: url(\"{}?v=1\") format(\"woff2\"); }}\n",
+                bg_name, icons_name, font_name
+            )
+        );
+        assert_eq!(
+            stylesheet.assets,
+            vec![
+                (dir.join("img/bg.png"), bg_name),
+                (normalize_path(&dir.join("../b/icons.svg")), icons_name),
+                (dir.join("font.woff2"), font_name),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn format_url_inlined() {
+        let dir = write_test_files(
+            "format_url_inlined",
+            &[
+                (
+                    "a.css",
+                    ".a { background: url(small.svg), url(large.png); }",
+                ),
+                ("small.svg", "<svg/>"),
+                ("large.png", "large file"),
+            ],
+        );
+
+        let mut stylesheet = Stylesheet {
+            inline_assets_limit: Some(6),
+            ..stylesheet()
+        };
+        let module = stylesheet.add_module(&dir.join("a.css")).unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            format!(
+                ".a__a__0 {{ background: url(\"data:image/svg+xml;base64,PHN2Zy8+\"), \
+                    url(\"large.{:016x}.png\"); }}\n",
+                hash(b"large file")
+            )
+        );
+        assert_eq!(module.assets.len(), 2);
+        assert_eq!(stylesheet.assets.len(), 1);
+    }
+
+    #[test]
+    fn format_url_percent_encoded() {
+        let dir = write_test_files(
+            "format_url_percent_encoded",
+            &[
+                ("a.css", ".a { background: url(a%20b.png); }"),
+                ("a b.png", "png"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        let module = stylesheet.add_module(&dir.join("a.css")).unwrap();
+        let file_name = format!("a b.{:016x}.png", hash(b"png"));
+
+        assert_eq!(
+            format!("{}", module),
+            format!(".a__a__0 {{ background: url(\"{}\"); }}\n", file_name)
+        );
+        assert_eq!(
+            stylesheet.assets,
+            vec![(dir.join("a b.png"), file_name)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn format_url_external() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                ".a { background: url(https://a.b/c.png), url('//a.b/c.png'), url(/c.png), \
+                    url(data:image/png;base64,AAAA), url(#c); content: 'url(c.png)'; }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            ".ast__a__0 { background: url(\"https://a.b/c.png\"), url(\"//a.b/c.png\"), \
+                url(\"/c.png\"), url(\"data:image/png;base64,AAAA\"), url(\"#c\"); \
+                content: 'url(c.png)'; }\n"
+        );
+    }
+
+    #[test]
+    fn error_is_url_asset_not_found() {
+        assert!(stylesheet()
+            .add_test_module(".a { background: url(not-found.png); }")
+            .is_err());
+    }
+
+    #[test]
+    fn format_import() {
+        let dir = write_test_files(
+            "format_import",
+            &[
+                (
+                    "a.css",
+                    "@import './b.css'; @import url(c.css); .a { color: red; }",
+                ),
+                ("b.css", ".b { color: green; }"),
+                ("c.css", "@import \"./b.css\"; .c { color: blue; }"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        stylesheet.add_module(&dir.join("a.css")).unwrap();
+
+        // imported modules are parsed once and rendered before importing module
+        assert_eq!(stylesheet.modules.len(), 3);
+        assert_eq!(
+            format!("{}", stylesheet),
+            ".b__b__0 { color: green; }\n\
+                .c__c__1 { color: blue; }\n\
+                .a__a__2 { color: red; }\n"
+        );
+        assert_eq!(
+            stylesheet
+                .exports(&stylesheet.modules[&dir.join("a.css")])
+                .unwrap(),
+            exports(&[("a", "a__a__2"), ("b", "b__b__0"), ("c", "c__c__1")])
+        );
+    }
+
+    #[test]
+    fn format_chunks() {
+        let dir = write_test_files(
+            "format_chunks",
+            &[
+                (
+                    "a.css",
+                    "@import './b.css'; .a { composes: c from './c.css'; color: red; }",
+                ),
+                ("b.css", ".b { color: green; }"),
+                ("c.css", ".c { color: blue; }"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        stylesheet.add_module(&dir.join("a.css")).unwrap();
+        stylesheet.add_module(&dir.join("c.css")).unwrap();
+
+        let chunks = |module_path: &str| {
+            stylesheet
+                .module_with_dependencies(&dir.join(module_path))
+                .iter()
+                .map(|module| module.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // chunk of module is preceded with chunks of modules it depends on
+        assert_eq!(
+            chunks("a.css"),
+            [
+                ".b__b__0 { color: green; }\n",
+                ".c__c__2 { color: blue; }\n",
+                ".a__a__1 { color: red; }\n"
+            ]
+        );
+        assert_eq!(chunks("c.css"), [".c__c__2 { color: blue; }\n"]);
+    }
+
+    #[test]
+    fn format_import_conditional() {
+        let dir = write_test_files(
+            "format_import_conditional",
+            &[
+                (
+                    "a.css",
+                    "@import url(\"b.css\") layer(base) supports(display: grid) screen; \
+                        @import 'b.css' layer; @import 'b.css' supports(not (display: grid));",
+                ),
+                ("b.css", ".b { color: green; }"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        stylesheet.add_module(&dir.join("a.css")).unwrap();
+
+        // conditionally imported module is not rendered separately
+        assert_eq!(
+            format!("{}", stylesheet),
+            "@media screen { @supports (display: grid) { @layer base { \
+                .b__b__0 { color: green; }\n}\n}\n}\n\
+                @layer { .b__b__0 { color: green; }\n}\n\
+                @supports not (display: grid) { .b__b__0 { color: green; }\n}\n"
+        );
+    }
+
+    #[test]
+    fn format_import_external() {
+        let mut stylesheet = stylesheet();
+        stylesheet
+            .add_test_module(".a {} @import url(https://a.b/c.css) screen;")
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", stylesheet),
+            "@import url(https://a.b/c.css) screen; "
+        );
+    }
+
+    #[test]
+    fn error_is_import_cycle() {
+        let dir = write_test_files(
+            "error_is_import_cycle",
+            &[
+                ("a.css", "@import 'b.css';"),
+                ("b.css", "@import 'c.css';"),
+                ("c.css", "@import 'b.css';"),
+            ],
+        );
+
+        let error = stylesheet().add_module(&dir.join("a.css")).unwrap_err();
+
+        assert_eq!(
+            format!("{}", error.root_cause()),
+            format!(
+                "Import cycle detected: {:?} -> {:?} -> {:?}",
+                dir.join("b.css"),
+                dir.join("c.css"),
+                dir.join("b.css")
+            )
+        );
+
+        let error = Stylesheet::default()
+            .add_modules([&dir.join("a.css")])
+            .unwrap_err();
+
+        assert_eq!(
+            format!("{}", error.root_cause()),
+            format!(
+                "Import cycle detected: {:?} -> {:?} -> {:?}",
+                dir.join("b.css"),
+                dir.join("c.css"),
+                dir.join("b.css")
+            )
+        );
+    }
+
+    #[test]
+    fn format_parallel() {
+        let dir = write_test_files(
+            "format_parallel",
+            &[
+                (
+                    "a.css",
+                    "@import './b.css'; @import 'http://x.com/y.css'; \
+                        .a { composes: d from './d.css'; background: url(e.png); }",
+                ),
+                ("b.css", "@import './c.css' screen; .b { color: green; }"),
+                (
+                    "c.css",
+                    "@supports (display: grid) { @import './d.css'; } .c { color: blue; }",
+                ),
+                ("d.css", ".d { color: red; } .a { color: black; }"),
+                ("e.png", "e"),
+                ("f.css", "@import './c.css'; .f { background: url(e.png); }"),
+            ],
+        );
+        let module_paths = [dir.join("f.css"), dir.join("a.css")];
+
+        let mut sequential = Stylesheet::default();
+        for module_path in &module_paths {
+            sequential.add_module(module_path).unwrap();
+        }
+
+        let mut parallel = Stylesheet::default();
+        parallel.add_modules(&module_paths).unwrap();
+
+        assert_eq!(parallel.modules.len(), 5);
+        assert_eq!(parallel.modules, sequential.modules);
+        assert_eq!(parallel.entries, sequential.entries);
+        assert_eq!(parallel.assets, sequential.assets);
+        assert_eq!(parallel.to_string(), sequential.to_string());
+    }
+
+    #[test]
+    fn format_grid() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                ".a { \
+                    grid-template-areas: \"head head\" \"nav  main\" '. main'; \
+                    grid-template-columns: [full-start] minmax(1em, 1fr) [main-start] \
+                        repeat(auto-fill, [col] 100px) [main-end]; \
+                    grid: auto-flow dense / 40px var(--width); \
+                } \
+                .b { grid-area: head; grid-row: nav-start / span 2; grid-column: 1 / -1; }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            ".ast__a__0 { \
+                grid-template-areas: \"ast__head__1 ast__head__1\" \"ast__nav__2  ast__main__3\" \
+                    '. ast__main__3'; \
+                grid-template-columns: [ast__full-start__4] minmax(1em, 1fr) \
+                    [ast__main__3-start] repeat(auto-fill, [ast__col__5] 100px) \
+                    [ast__main__3-end]; \
+                grid: auto-flow dense / 40px var(--width); \
+            }\n\
+            .ast__b__6 { grid-area: ast__head__1; grid-row: ast__nav__2-start / span 2; \
+                grid-column: 1 / -1; }\n"
+        );
+    }
+
+    #[test]
+    fn format_grid_area_with_suffix() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                ".g { \
+                    grid-template-areas: \"side-end main\"; \
+                    grid-template-columns: [content-start] 1fr [content-end]; \
+                } \
+                .s { grid-area: side-end; grid-column: side-end-start / main-end; \
+                    grid-row: content; }",
+            )
+            .unwrap();
+
+        // suffixes are only kept for implicit lines of areas, and area names can have suffixes
+        assert_eq!(
+            format!("{}", module),
+            ".ast__g__0 { \
+                grid-template-areas: \"ast__side-end__1 ast__main__2\"; \
+                grid-template-columns: [ast__content__3-start] 1fr [ast__content__3-end]; \
+            }\n\
+            .ast__s__4 { grid-area: ast__side-end__1; \
+                grid-column: ast__side-end__1-start / ast__main__2-end; \
+                grid-row: ast__content__3; }\n"
+        );
+    }
+
+    #[test]
+    fn format_counters() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                "@counter-style stars { system: extends decimal; fallback: dots; } \
+                .a { counter-reset: items 1 list-item reversed(steps); list-style: stars inside; } \
+                .b { counter-increment: items -1; list-style-type: square; } \
+                .b::before { content: counter(items, stars) \". \" counters(steps, '.', disc); } \
+                .c::before { content: COUNTER(items) url(\"/counter.png\"); } \
+                .d::before { content: 'counter' attr(counter); }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            "@counter-style ast__stars__0 { system: extends decimal; fallback: ast__dots__1; }\n\
+            .ast__a__2 { counter-reset: ast__items__3 1 list-item reversed(ast__steps__4); \
+                list-style: ast__stars__0 inside; }\n\
+            .ast__b__5 { counter-increment: ast__items__3 -1; list-style-type: square; }\n\
+            .ast__b__5::before { content: counter(ast__items__3, ast__stars__0) \". \" \
+                counters(ast__steps__4, '.', disc); }\n\
+            .ast__c__6::before { content: COUNTER(ast__items__3) url(\"/counter.png\"); }\n\
+            .ast__d__7::before { content: 'counter' attr(counter); }\n"
+        );
+    }
+
+    #[test]
+    fn format_counter_style_descriptors_outside_counter_style() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                ".a { system: foo; fallback: bar; } \
+                @media screen { .b { system: foo; } } \
+                @counter-style stars { system: cyclic; .c { fallback: baz; } }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            ".ast__a__0 { system: foo; fallback: bar; }\n\
+            @media screen { .ast__b__1 { system: foo; }\n}\n\
+            @counter-style ast__stars__2 { system: cyclic; .ast__c__3 { fallback: baz; }\n}\n"
+        );
+    }
+
+    #[test]
+    fn format_animation() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                ".a { animation: 1s ease-in infinite spin, fade 2s steps(4, end) 1s 3 reverse; } \
+                .b { animation: ease ease .5s, linear 1s none, 1s paused running both; } \
+                .c { animation-name: spin, none, :global(slide), ease; }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            ".ast__a__0 { animation: 1s ease-in infinite ast__spin__1, \
+                ast__fade__2 2s steps(4, end) 1s 3 reverse; }\n\
+            .ast__b__3 { animation: ease ast__ease__4 .5s, linear 1s none, \
+                1s paused ast__running__5 both; }\n\
+            .ast__c__6 { animation-name: ast__spin__1, none, slide, ast__ease__4; }\n"
+        );
+    }
+
+    #[test]
+    fn format_at_rule_shapes() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                "@layer base, components; \
+                @layer base{.a{color:red}} \
+                @layer { .b { color: green; } } \
+                @font-face { font-family: foo; src: local(foo); } \
+                @page :first { margin: 1in; @top-left { content: 'foo'; } } \
+                @media(min-width: 100px) { @page { margin: 0; } } \
+                @font-feature-values foo { @swash { fancy: 1; } }",
+            )
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            "@layer base, components; \
+            @layer base { .ast__a__0 { color: red; }\n}\n\
+            @layer { .ast__b__1 { color: green; }\n}\n\
+            @font-face { font-family: foo; src: local(foo); }\n\
+            @page :first { margin: 1in; @top-left { content: 'foo'; }\n}\n\
+            @media (min-width: 100px) { @page { margin: 0; }\n}\n\
+            @font-feature-values foo { @swash { fancy: 1; }\n}\n"
+        );
+    }
+
+    #[test]
+    fn parses_at_rule_without_rule() {
+        assert_eq!(
+            stylesheet()
+                .add_test_module("@font-face { font-family: foo; }")
+                .unwrap()
+                .children,
+            vec![Child::AtRule {
+                name: Some("font-face".into()),
+                rule: None,
+                children: Some(vec![Child::Property {
+                    name: Some("font-family".into()),
+                    value: Some("foo".into()),
+                }]),
+            }]
+        );
+    }
+
+    #[test]
+    fn format_selector_tokens() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(
+                "a[href$=\".pdf\"] /* .b */ .c, .d\\.e:is(.f, :global .g), .\\31 h { top: 0 } \
+                .i { content: '.j'; color: red; *zoom: 1; } /* .k {} */",
+            )
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            "a[href$=\".pdf\"] /* .b */ .ast__c__0, .ast__d\\.e__1:is(.ast__f__2, .g), \
+                .ast__1h__3 { top: 0; }\n\
+            .ast__i__4 { content: '.j'; color: red; *zoom: 1; }\n/* .k {} */ "
+        );
+        assert_eq!(
+            module.names,
+            vec![
+                ("c".into(), "ast__c__0".into()),
+                ("d.e".into(), "ast__d.e__1".into()),
+                ("f".into(), "ast__f__2".into()),
+                ("1h".into(), "ast__1h__3".into()),
+                ("i".into(), "ast__i__4".into()),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn format_legacy_hacks() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(".a { *zoom: 1; //color: red; #top: 0 } .b { _top: 0; }")
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            ".ast__a__0 { *zoom: 1; //color: red; #top: 0 }\n.ast__b__1 { _top: 0; }\n"
+        );
+    }
+
+    #[test]
+    fn format_invalid_top_level_rules() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module("; .a { color: red; } } b; .c { top: 0; } .d")
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            ".ast__a__0 { color: red; }\n.ast__c__1 { top: 0; }\n"
+        );
+    }
+
+    #[test]
+    fn format_charset() {
+        let dir = write_test_files(
+            "format_charset",
+            &[
+                ("a.css", "@charset \"utf-8\"; @import './b.css'; .a {}"),
+                ("b.css", "@charset \"utf-8\"; .b {}"),
+            ],
+        );
+
+        let mut stylesheet = stylesheet();
+        stylesheet.add_module(&dir.join("a.css")).unwrap();
+
+        assert!(!format!("{}", stylesheet).contains("@charset"));
+    }
+
+    #[test]
+    fn format_unclosed_comment() {
+        let mut rules = stylesheet();
+        rules.add_test_module(".a{color:red}/*x").unwrap();
+
+        assert_eq!(format!("{}", rules), ".ast__a__0 { color: red; }\n/*x*/ ");
+
+        let mut external = stylesheet();
+        external
+            .add_test_module("@import url(https://a.b/c.css) /* x")
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", external),
+            "@import url(https://a.b/c.css) /* x*/; "
+        );
+    }
+
+    #[test]
+    fn format_custom_property_with_block() {
+        let mut stylesheet = stylesheet();
+        let module = stylesheet
+            .add_test_module(".a { --b: { c: d }; e: f; &:hover { g: h } }")
+            .unwrap();
+
+        assert_eq!(
+            format!("{}", module),
+            ".ast__a__0 { --b: { c: d }; e: f; &:hover { g: h; }\n}\n"
+        );
+    }
+
     #[test]
     fn error_is_unclosed_block() {
-        assert!(Stylesheet::default().add_test_module("p {").is_err());
+        assert!(stylesheet().add_test_module("p {").is_err());
     }
 
     #[test]
     fn error_is_property_without_value() {
-        assert!(Stylesheet::default().add_test_module("p {color;}").is_err());
+        assert!(stylesheet().add_test_module("p {color;}").is_err());
     }
 }
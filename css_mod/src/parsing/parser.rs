@@ -1,31 +1,23 @@
+use super::tokenizer::{tokenize, Token, TokenKind};
 use anyhow::{anyhow, Result};
-use pest::iterators::Pair;
-use pest::iterators::Pairs;
-use pest::Parser;
-use pest_derive::*;
+use std::borrow::Cow;
 use thiserror::Error;
 
-#[derive(Parser)]
-#[grammar = "parsing/parser.pest"]
-struct Grammar;
-
 #[derive(Debug, PartialEq, Error)]
 pub enum Error {
     #[error("{0}")]
-    Parser(pest::error::Error<Rule>),
-    #[error("{0}")]
-    AtPair(String),
+    AtPosition(String),
 }
 
-impl<'p> From<Pair<'p, Rule>> for Error {
-    fn from(pair: Pair<'p, Rule>) -> Self {
-        let message = match pair.as_rule() {
-            Rule::error_block_not_terminated => "Unterminated ruleset",
-            _ => "Unexpected error",
-        };
-        let start = pair.as_span().start_pos();
-        let line = start.line_of();
-        let (line_no, col_no) = start.line_col();
+impl Error {
+    fn new(input: &str, position: usize, message: &str) -> Self {
+        let line_start = input[..position].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = input[position..]
+            .find('\n')
+            .map_or(input.len(), |index| position + index);
+        let line = &input[line_start..line_end];
+        let line_no = input[..line_start].matches('\n').count() + 1;
+        let col_no = input[line_start..position].chars().count() + 1;
         let line_no_len = format!("{}", line_no).len();
         let mut spacing = String::new();
 
@@ -33,7 +25,7 @@ impl<'p> From<Pair<'p, Rule>> for Error {
             spacing.push(' ');
         }
 
-        Error::AtPair(format!(
+        Error::AtPosition(format!(
             " {line_no:indent$} ┊ {line}\n    {spacing:col_no$}│\n    {spacing:col_no$}╰ {message} at {line_no}:{col_no}",
             spacing = spacing,
             indent = spacing.len(),
@@ -45,21 +37,308 @@ impl<'p> From<Pair<'p, Rule>> for Error {
     }
 }
 
-fn parse(rule: Rule, input: &str) -> Result<Pairs<Rule>> {
-    match Grammar::parse(rule, input) {
-        Ok(pairs) => Ok(pairs),
-        Err(error) => Err(anyhow!(Error::Parser(error))),
+/// Node of stylesheet, which holds tokens of preludes and values as is, so they can be
+/// transformed depending on the rule or property they belong to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node<'i> {
+    AtRule {
+        name: &'i str,
+        prelude: Vec<Token<'i>>,
+        // block of the rule, or none if rule is terminated with semicolon (eg. `@import "a.css";`)
+        block: Option<Vec<Node<'i>>>,
+    },
+    QualifiedRule {
+        prelude: Vec<Token<'i>>,
+        block: Vec<Node<'i>>,
+    },
+    Declaration {
+        name: &'i str,
+        value: Vec<Token<'i>>,
+    },
+    // regular comment, or legacy line comment inside block (eg. `*zoom: 1;`)
+    Comment(&'i str),
+}
+
+struct Parser<'i> {
+    input: &'i str,
+    tokens: Vec<Token<'i>>,
+    position: usize,
+}
+
+impl<'i> Parser<'i> {
+    fn peek(&self) -> Option<TokenKind> {
+        self.tokens.get(self.position).map(|token| token.kind)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.input.len(), |token| token.start)
+    }
+
+    fn error(&self, position: usize, message: &str) -> anyhow::Error {
+        anyhow!(Error::new(self.input, position, message))
+    }
+
+    fn warning(&self, position: usize, message: &str) {
+        log::warn!("{}", Error::new(self.input, position, message));
+    }
+
+    /// Consumes token, or whole block if token opens it.
+    fn consume_component_value(&mut self) {
+        let mut closing = Vec::new();
+
+        while let Some(kind) = self.peek() {
+            self.position += 1;
+
+            match kind {
+                TokenKind::Function | TokenKind::OpenParen => closing.push(TokenKind::CloseParen),
+                TokenKind::OpenSquare => closing.push(TokenKind::CloseSquare),
+                TokenKind::OpenCurly => closing.push(TokenKind::CloseCurly),
+                kind if closing.last() == Some(&kind) => {
+                    closing.pop();
+                }
+                _ => {}
+            }
+
+            if closing.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Consumes component values until one of given tokens, which is left unconsumed.
+    fn consume_until(&mut self, kinds: &[TokenKind]) -> Vec<Token<'i>> {
+        let start = self.position;
+
+        while self.peek().is_some_and(|kind| !kinds.contains(&kind)) {
+            self.consume_component_value();
+        }
+
+        trim(&self.tokens[start..self.position]).to_vec()
+    }
+
+    /// Checks whether block item at current position is nested rule rather than declaration, by
+    /// looking ahead for its block.
+    fn is_nested_rule(&mut self) -> bool {
+        let start = self.position;
+
+        // custom properties may contain blocks in their values
+        if self.tokens[start].kind == TokenKind::Ident && self.tokens[start].value.starts_with("--")
+        {
+            return false;
+        }
+
+        self.consume_until(&[
+            TokenKind::OpenCurly,
+            TokenKind::Semicolon,
+            TokenKind::CloseCurly,
+        ]);
+
+        let is_nested_rule = self.peek() == Some(TokenKind::OpenCurly);
+        self.position = start;
+
+        is_nested_rule
+    }
+
+    /// Consumes list of rules, either at the top level of stylesheet or inside a block, which can
+    /// also contain declarations.
+    fn rules(&mut self, block_start: Option<usize>) -> Result<Vec<Node<'i>>> {
+        let mut nodes = Vec::new();
+
+        loop {
+            match self.peek() {
+                None => match block_start {
+                    Some(block_start) => return Err(self.error(block_start, "Unterminated block")),
+                    None => return Ok(nodes),
+                },
+                Some(TokenKind::CloseCurly) => match block_start {
+                    Some(_) => {
+                        self.position += 1;
+                        return Ok(nodes);
+                    }
+                    // invalid closing brace at the top level is dropped (eg. `.a {} } .b {}`)
+                    None => {
+                        self.warning(self.offset(), "Skipping unexpected closing brace");
+                        self.position += 1;
+                    }
+                },
+                Some(TokenKind::Whitespace | TokenKind::Cdo | TokenKind::Cdc) => {
+                    self.position += 1;
+                }
+                Some(TokenKind::Semicolon) if block_start.is_some() => {
+                    self.position += 1;
+                }
+                Some(TokenKind::Comment) => {
+                    nodes.push(Node::Comment(self.tokens[self.position].source));
+                    self.position += 1;
+                }
+                Some(TokenKind::AtKeyword) => nodes.push(self.at_rule()?),
+                Some(_) if block_start.is_some() && !self.is_nested_rule() => {
+                    nodes.push(self.declaration()?)
+                }
+                Some(_) => nodes.extend(self.qualified_rule()?),
+            }
+        }
+    }
+
+    fn at_rule(&mut self) -> Result<Node<'i>> {
+        let source = self.tokens[self.position].source;
+        let name = &source[1..];
+        self.position += 1;
+
+        let prelude = self.consume_until(&[
+            TokenKind::OpenCurly,
+            TokenKind::Semicolon,
+            TokenKind::CloseCurly,
+        ]);
+
+        let block = match self.peek() {
+            Some(TokenKind::OpenCurly) => {
+                let block_start = self.offset();
+                self.position += 1;
+                Some(self.rules(Some(block_start))?)
+            }
+            Some(TokenKind::Semicolon) => {
+                self.position += 1;
+                None
+            }
+            _ => None,
+        };
+
+        Ok(Node::AtRule {
+            name,
+            prelude,
+            block,
+        })
+    }
+
+    /// Consumes qualified rule, or skips it if it has no block (eg. stray `a;` or `;`).
+    fn qualified_rule(&mut self) -> Result<Option<Node<'i>>> {
+        let start = self.offset();
+        let prelude = self.consume_until(&[
+            TokenKind::OpenCurly,
+            TokenKind::Semicolon,
+            TokenKind::CloseCurly,
+        ]);
+
+        match self.peek() {
+            Some(TokenKind::OpenCurly) => {}
+            Some(TokenKind::Semicolon) => {
+                self.warning(start, "Skipping rule without block");
+                self.position += 1;
+                return Ok(None);
+            }
+            // closing brace is left to be consumed by the list of rules
+            _ => {
+                self.warning(start, "Skipping rule without block");
+                return Ok(None);
+            }
+        }
+
+        let block_start = self.offset();
+        self.position += 1;
+
+        Ok(Some(Node::QualifiedRule {
+            prelude,
+            block: self.rules(Some(block_start))?,
+        }))
     }
+
+    fn declaration(&mut self) -> Result<Node<'i>> {
+        let Token {
+            kind,
+            start,
+            source,
+            ..
+        } = self.tokens[self.position];
+
+        if matches!(
+            kind,
+            TokenKind::Delim('*') | TokenKind::Delim('/') | TokenKind::Hash
+        ) {
+            // legacy hacks commenting out declarations (eg. `*zoom: 1;` or `//color: red;`)
+            self.consume_until(&[TokenKind::Semicolon, TokenKind::CloseCurly]);
+
+            if self.peek() == Some(TokenKind::Semicolon) {
+                self.position += 1;
+            }
+
+            return Ok(Node::Comment(&self.input[start..self.offset()]));
+        }
+
+        if kind != TokenKind::Ident {
+            return Err(self.error(start, "Expected property name"));
+        }
+
+        let name = source;
+        self.position += 1;
+
+        while matches!(
+            self.peek(),
+            Some(TokenKind::Whitespace | TokenKind::Comment)
+        ) {
+            self.position += 1;
+        }
+
+        if self.peek() != Some(TokenKind::Colon) {
+            return Err(self.error(self.offset(), "Expected colon after property name"));
+        }
+
+        self.position += 1;
+
+        let value = self.consume_until(&[TokenKind::Semicolon, TokenKind::CloseCurly]);
+
+        if self.peek() == Some(TokenKind::Semicolon) {
+            self.position += 1;
+        }
+
+        Ok(Node::Declaration { name, value })
+    }
+}
+
+/// Strips whitespace tokens from both ends.
+pub fn trim<'t, 'i>(tokens: &'t [Token<'i>]) -> &'t [Token<'i>] {
+    let is_whitespace = |token: &Token| token.kind == TokenKind::Whitespace;
+    let start = tokens
+        .iter()
+        .position(|token| !is_whitespace(token))
+        .unwrap_or(tokens.len());
+    let end = tokens
+        .iter()
+        .rposition(|token| !is_whitespace(token))
+        .map_or(start, |index| index + 1);
+
+    &tokens[start..end]
 }
 
-pub fn animation(animation: &str) -> Result<Pairs<Rule>> {
-    parse(Rule::animation, animation)
+/// Concatenates source text of tokens.
+pub fn to_css(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token.kind {
+            TokenKind::Comment => close_comment(token.source),
+            _ => Cow::Borrowed(token.source),
+        })
+        .collect()
 }
 
-pub fn stylesheet(stylesheet: &str) -> Result<Pairs<Rule>> {
-    parse(Rule::stylesheet, stylesheet)
+/// Closes comment left open at the end of input (eg. `/* a`), so it doesn't comment out CSS
+/// following it in output.
+pub fn close_comment(source: &str) -> Cow<'_, str> {
+    if source.starts_with("/*") && (source.len() < 4 || !source.ends_with("*/")) {
+        Cow::Owned(format!("{}*/", source))
+    } else {
+        Cow::Borrowed(source)
+    }
 }
 
-pub fn selector(selector: &str) -> Result<Pairs<Rule>> {
-    parse(Rule::selector, selector)
+pub fn stylesheet(input: &str) -> Result<Vec<Node<'_>>> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input),
+        position: 0,
+    };
+
+    parser.rules(None)
 }
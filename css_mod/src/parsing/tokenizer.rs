@@ -0,0 +1,595 @@
+//! Tokenizer of CSS source, which follows tokenization algorithm from CSS Syntax Module Level 3
+//! (https://www.w3.org/TR/css-syntax-3/#tokenization).
+//!
+//! Unlike the spec, input is not preprocessed and comments are emitted as tokens, so source text
+//! of tokens can be concatenated back to reproduce the input exactly.
+
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Ident,
+    Function,
+    AtKeyword,
+    Hash,
+    String,
+    BadString,
+    Url,
+    BadUrl,
+    Delim(char),
+    Number,
+    Percentage,
+    Dimension,
+    Whitespace,
+    Comment,
+    Cdo,
+    Cdc,
+    Colon,
+    Semicolon,
+    Comma,
+    OpenSquare,
+    CloseSquare,
+    OpenParen,
+    CloseParen,
+    OpenCurly,
+    CloseCurly,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'i> {
+    pub kind: TokenKind,
+    // unescaped value: name of identifiers, functions, at-keywords and hashes, contents of strings
+    // and URLs, or unit of dimensions
+    pub value: Cow<'i, str>,
+    // source text of the token
+    pub source: &'i str,
+    // byte offset of the token in the input
+    pub start: usize,
+}
+
+impl Token<'_> {
+    /// Checks whether token is identifier with given name (case-insensitive).
+    pub fn is_ident(&self, name: &str) -> bool {
+        self.kind == TokenKind::Ident && self.value.eq_ignore_ascii_case(name)
+    }
+
+    /// Checks whether token is function with given name (case-insensitive).
+    pub fn is_function(&self, name: &str) -> bool {
+        self.kind == TokenKind::Function && self.value.eq_ignore_ascii_case(name)
+    }
+}
+
+pub struct Tokenizer<'i> {
+    input: &'i str,
+    position: usize,
+}
+
+impl<'i> Tokenizer<'i> {
+    pub fn new(input: &'i str) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.input[self.position..].chars().nth(offset)
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn starts_with(&self, pattern: &str) -> bool {
+        self.input[self.position..].starts_with(pattern)
+    }
+
+    fn starts_ident(&self) -> bool {
+        starts_ident(self.peek(0), self.peek(1), self.peek(2))
+    }
+
+    fn starts_number(&self) -> bool {
+        match self.peek(0) {
+            Some('+' | '-') => match self.peek(1) {
+                Some(c) if c.is_ascii_digit() => true,
+                Some('.') => self.peek(2).is_some_and(|c| c.is_ascii_digit()),
+                _ => false,
+            },
+            Some('.') => self.peek(1).is_some_and(|c| c.is_ascii_digit()),
+            Some(c) => c.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    fn consume_newline(&mut self) {
+        if self.starts_with("\r\n") {
+            self.position += 2;
+        } else {
+            self.advance();
+        }
+    }
+
+    fn consume_whitespace(&mut self) {
+        while self.peek(0).is_some_and(is_whitespace) {
+            self.advance();
+        }
+    }
+
+    fn consume_comment(&mut self) {
+        self.position += 2;
+        self.position = match self.input[self.position..].find("*/") {
+            Some(end) => self.position + end + 2,
+            None => self.input.len(),
+        };
+    }
+
+    /// Consumes escaped code point, assuming that backslash was already consumed.
+    fn consume_escape(&mut self) -> char {
+        match self.peek(0) {
+            Some(c) if c.is_ascii_hexdigit() => {
+                let mut code = 0;
+                let mut digits = 0;
+
+                // escape consists of up to 6 hex digits
+                while let Some(digit) = self.peek(0).and_then(|c| c.to_digit(16)) {
+                    if digits == 6 {
+                        break;
+                    }
+
+                    code = code * 16 + digit;
+                    digits += 1;
+                    self.advance();
+                }
+
+                if self.peek(0).is_some_and(is_whitespace) {
+                    self.consume_newline();
+                }
+
+                char::from_u32(code)
+                    .filter(|c| *c != '\0')
+                    .unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            Some(_) => self.advance().unwrap_or(char::REPLACEMENT_CHARACTER),
+            None => char::REPLACEMENT_CHARACTER,
+        }
+    }
+
+    fn consume_ident_sequence(&mut self) -> Cow<'i, str> {
+        let start = self.position;
+        let mut value: Option<String> = None;
+
+        loop {
+            match self.peek(0) {
+                Some(c) if is_ident_char(c) => {
+                    self.advance();
+
+                    if let Some(value) = &mut value {
+                        value.push(c);
+                    }
+                }
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(1)) => {
+                    let value =
+                        value.get_or_insert_with(|| self.input[start..self.position].into());
+                    self.advance();
+                    value.push(self.consume_escape());
+                }
+                _ => break,
+            }
+        }
+
+        match value {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&self.input[start..self.position]),
+        }
+    }
+
+    fn consume_string(&mut self) -> (TokenKind, Cow<'i, str>) {
+        let quote = self.advance();
+        let mut value = String::new();
+
+        loop {
+            match self.peek(0) {
+                None => break,
+                Some(c) if Some(c) == quote => {
+                    self.advance();
+                    break;
+                }
+                Some(c) if is_newline(c) => return (TokenKind::BadString, value.into()),
+                Some('\\') => {
+                    self.advance();
+
+                    match self.peek(0) {
+                        None => {}
+                        Some(c) if is_newline(c) => self.consume_newline(),
+                        Some(_) => value.push(self.consume_escape()),
+                    }
+                }
+                Some(c) => {
+                    self.advance();
+                    value.push(c);
+                }
+            }
+        }
+
+        (TokenKind::String, value.into())
+    }
+
+    fn consume_number(&mut self) {
+        let consume_digits = |tokenizer: &mut Self| {
+            while tokenizer.peek(0).is_some_and(|c| c.is_ascii_digit()) {
+                tokenizer.advance();
+            }
+        };
+
+        if matches!(self.peek(0), Some('+' | '-')) {
+            self.advance();
+        }
+
+        consume_digits(self);
+
+        if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+            self.advance();
+            consume_digits(self);
+        }
+
+        if matches!(self.peek(0), Some('e' | 'E')) {
+            let exponent = match self.peek(1) {
+                Some('+' | '-') => self.peek(2),
+                c => c,
+            };
+
+            if exponent.is_some_and(|c| c.is_ascii_digit()) {
+                self.advance();
+
+                if matches!(self.peek(0), Some('+' | '-')) {
+                    self.advance();
+                }
+
+                consume_digits(self);
+            }
+        }
+    }
+
+    fn consume_numeric(&mut self) -> (TokenKind, Cow<'i, str>) {
+        self.consume_number();
+
+        if self.starts_ident() {
+            (TokenKind::Dimension, self.consume_ident_sequence())
+        } else if self.peek(0) == Some('%') {
+            self.advance();
+            (TokenKind::Percentage, Cow::Borrowed(""))
+        } else {
+            (TokenKind::Number, Cow::Borrowed(""))
+        }
+    }
+
+    fn consume_ident_like(&mut self) -> (TokenKind, Cow<'i, str>) {
+        let name = self.consume_ident_sequence();
+
+        if self.peek(0) != Some('(') {
+            return (TokenKind::Ident, name);
+        }
+
+        self.advance();
+
+        if name.eq_ignore_ascii_case("url") {
+            let quote = self.input[self.position..]
+                .chars()
+                .find(|c| !is_whitespace(*c));
+
+            // url with quoted string is tokenized as regular function
+            if !matches!(quote, Some('"' | '\'')) {
+                return self.consume_url();
+            }
+        }
+
+        (TokenKind::Function, name)
+    }
+
+    fn consume_url(&mut self) -> (TokenKind, Cow<'i, str>) {
+        let mut value = String::new();
+
+        self.consume_whitespace();
+
+        loop {
+            match self.advance() {
+                None | Some(')') => break,
+                Some(c) if is_whitespace(c) => {
+                    self.consume_whitespace();
+
+                    match self.peek(0) {
+                        None => break,
+                        Some(')') => {
+                            self.advance();
+                            break;
+                        }
+                        Some(_) => return self.consume_bad_url(),
+                    }
+                }
+                Some('"' | '\'' | '(') => return self.consume_bad_url(),
+                Some(c) if is_non_printable(c) => return self.consume_bad_url(),
+                Some('\\') => {
+                    if is_valid_escape(Some('\\'), self.peek(0)) {
+                        value.push(self.consume_escape());
+                    } else {
+                        return self.consume_bad_url();
+                    }
+                }
+                Some(c) => value.push(c),
+            }
+        }
+
+        (TokenKind::Url, value.into())
+    }
+
+    fn consume_bad_url(&mut self) -> (TokenKind, Cow<'i, str>) {
+        loop {
+            match self.advance() {
+                None | Some(')') => break,
+                Some('\\') if is_valid_escape(Some('\\'), self.peek(0)) => {
+                    self.consume_escape();
+                }
+                Some(_) => {}
+            }
+        }
+
+        (TokenKind::BadUrl, Cow::Borrowed(""))
+    }
+}
+
+impl<'i> Iterator for Tokenizer<'i> {
+    type Item = Token<'i>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.position;
+        let c = self.peek(0)?;
+
+        let single = |tokenizer: &mut Self, kind| {
+            tokenizer.advance();
+            (kind, Cow::Borrowed(""))
+        };
+
+        let (kind, value) = match c {
+            '/' if self.peek(1) == Some('*') => {
+                self.consume_comment();
+                (TokenKind::Comment, Cow::Borrowed(""))
+            }
+            c if is_whitespace(c) => {
+                self.consume_whitespace();
+                (TokenKind::Whitespace, Cow::Borrowed(""))
+            }
+            '"' | '\'' => self.consume_string(),
+            '#' if self.peek(1).is_some_and(is_ident_char)
+                || is_valid_escape(self.peek(1), self.peek(2)) =>
+            {
+                self.advance();
+                (TokenKind::Hash, self.consume_ident_sequence())
+            }
+            '(' => single(self, TokenKind::OpenParen),
+            ')' => single(self, TokenKind::CloseParen),
+            '[' => single(self, TokenKind::OpenSquare),
+            ']' => single(self, TokenKind::CloseSquare),
+            '{' => single(self, TokenKind::OpenCurly),
+            '}' => single(self, TokenKind::CloseCurly),
+            ',' => single(self, TokenKind::Comma),
+            ':' => single(self, TokenKind::Colon),
+            ';' => single(self, TokenKind::Semicolon),
+            '+' | '-' | '.' if self.starts_number() => self.consume_numeric(),
+            '-' if self.starts_with("-->") => {
+                self.position += 3;
+                (TokenKind::Cdc, Cow::Borrowed(""))
+            }
+            '<' if self.starts_with("<!--") => {
+                self.position += 4;
+                (TokenKind::Cdo, Cow::Borrowed(""))
+            }
+            '@' if starts_ident(self.peek(1), self.peek(2), self.peek(3)) => {
+                self.advance();
+                (TokenKind::AtKeyword, self.consume_ident_sequence())
+            }
+            c if c.is_ascii_digit() => self.consume_numeric(),
+            c if (is_ident_start(c) || c == '-' || c == '\\') && self.starts_ident() => {
+                self.consume_ident_like()
+            }
+            c => single(self, TokenKind::Delim(c)),
+        };
+
+        Some(Token {
+            kind,
+            value,
+            source: &self.input[start..self.position],
+            start,
+        })
+    }
+}
+
+pub fn tokenize(input: &str) -> Vec<Token<'_>> {
+    Tokenizer::new(input).collect()
+}
+
+fn is_newline(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\x0C')
+}
+
+fn is_whitespace(c: char) -> bool {
+    is_newline(c) || matches!(c, ' ' | '\t')
+}
+
+fn is_non_printable(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0B' | '\x0E'..='\x1F' | '\x7F')
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '\0' || !c.is_ascii()
+}
+
+/// Checks whether character can be a part of identifier without escaping.
+pub fn is_ident_char(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_valid_escape(first: Option<char>, second: Option<char>) -> bool {
+    first == Some('\\') && !second.is_some_and(is_newline)
+}
+
+fn starts_ident(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+    match first {
+        Some('-') => {
+            second.is_some_and(|c| is_ident_start(c) || c == '-') || is_valid_escape(second, third)
+        }
+        Some('\\') => is_valid_escape(first, second),
+        Some(c) => is_ident_start(c),
+        None => false,
+    }
+}
+
+/// Serializes identifier, escaping characters which are not allowed in identifiers
+/// (https://www.w3.org/TR/cssom-1/#serialize-an-identifier).
+pub fn serialize_identifier(value: &str) -> String {
+    let mut result = String::new();
+    let starts_with_dash = value.starts_with('-');
+
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '\0' => result.push(char::REPLACEMENT_CHARACTER),
+            '\x01'..='\x1F' | '\x7F' => result.push_str(&format!("\\{:x} ", c as u32)),
+            '0'..='9' if index == 0 || (index == 1 && starts_with_dash) => {
+                result.push_str(&format!("\\{:x} ", c as u32))
+            }
+            '-' if index == 0 && value.len() == 1 => result.push_str("\\-"),
+            c if is_ident_char(c) => result.push(c),
+            c => {
+                result.push('\\');
+                result.push(c);
+            }
+        }
+    }
+
+    result
+}
+
+/// Serializes string, wrapping it with double quotes
+/// (https://www.w3.org/TR/cssom-1/#serialize-a-string).
+pub fn serialize_string(value: &str) -> String {
+    let mut result = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '\0' => result.push(char::REPLACEMENT_CHARACTER),
+            '\x01'..='\x1F' | '\x7F' => result.push_str(&format!("\\{:x} ", c as u32)),
+            '"' | '\\' => {
+                result.push('\\');
+                result.push(c);
+            }
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<(TokenKind, String)> {
+        tokenize(input)
+            .into_iter()
+            .map(|token| (token.kind, token.value.into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn tokenizes_selector() {
+        assert_eq!(
+            kinds(r#"a[href$=".pdf"]/**/.b"#),
+            vec![
+                (TokenKind::Ident, "a".into()),
+                (TokenKind::OpenSquare, "".into()),
+                (TokenKind::Ident, "href".into()),
+                (TokenKind::Delim('$'), "".into()),
+                (TokenKind::Delim('='), "".into()),
+                (TokenKind::String, ".pdf".into()),
+                (TokenKind::CloseSquare, "".into()),
+                (TokenKind::Comment, "".into()),
+                (TokenKind::Delim('.'), "".into()),
+                (TokenKind::Ident, "b".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_escapes() {
+        assert_eq!(
+            kinds(
+                r"\31 0\.5 -\-a #\@b 'c\'\
+d'"
+            ),
+            vec![
+                (TokenKind::Ident, "10.5".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::Ident, "--a".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::Hash, "@b".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::String, "c'd".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_numbers() {
+        assert_eq!(
+            kinds("+.5 -1e3px 10% 1e-x -x"),
+            vec![
+                (TokenKind::Number, "".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::Dimension, "px".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::Percentage, "".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::Dimension, "e-x".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::Ident, "-x".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_urls() {
+        assert_eq!(
+            kinds("url( a;b.png ) URL('c') url(d e)"),
+            vec![
+                (TokenKind::Url, "a;b.png".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::Function, "URL".into()),
+                (TokenKind::String, "c".into()),
+                (TokenKind::CloseParen, "".into()),
+                (TokenKind::Whitespace, "".into()),
+                (TokenKind::BadUrl, "".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_reproduce_input() {
+        let input = "@media (x) { .a\\:b::before { content: '\\'' } } <!-- --> /* c";
+
+        assert_eq!(
+            tokenize(input)
+                .iter()
+                .map(|token| token.source)
+                .collect::<String>(),
+            input
+        );
+    }
+
+    #[test]
+    fn serializes_identifier() {
+        assert_eq!(serialize_identifier("a-b_c"), "a-b_c");
+        assert_eq!(serialize_identifier("1a"), "\\31 a");
+        assert_eq!(serialize_identifier("-1"), "-\\31 ");
+        assert_eq!(serialize_identifier("-"), "\\-");
+        assert_eq!(serialize_identifier("a.b:c"), "a\\.b\\:c");
+    }
+}
@@ -3,22 +3,75 @@ use serde::Deserialize;
 use std::env;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 pub fn write_file(file_path: &Path, content: String) -> Result<()> {
     let dir_path = file_path
         .parent()
         .context("Failed to get parent directory")?;
 
-    create_dir_all(&dir_path)?;
+    create_dir_all(dir_path)?;
 
-    let mut file = File::create(&file_path)
+    let mut file = File::create(file_path)
         .with_context(|| format!("Failed to create file: {:?}", file_path))?;
     file.write_all(content.as_bytes())?;
 
     Ok(())
 }
 
+/// Writes files into empty temporary directory of particular test, and gets path of that
+/// directory.
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+pub fn write_test_files(test_name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join("css_mod_tests").join(test_name);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    for (file_name, content) in files {
+        write_file(&dir.join(file_name), content.to_string()).unwrap();
+    }
+
+    dir
+}
+
+/// Gets hash of content.
+///
+/// Hash is stable across compilations, platforms and compiler versions, so it is safe to use it
+/// for naming output files.
+pub fn hash(content: &[u8]) -> u64 {
+    xxhash_rust::xxh3::xxh3_64(content)
+}
+
+/// Gets MIME type of file by its extension.
+pub fn mime_type(file_path: &Path) -> &'static str {
+    let extension = file_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "apng" => "image/apng",
+        "avif" => "image/avif",
+        "bmp" => "image/bmp",
+        "gif" => "image/gif",
+        "ico" | "cur" => "image/x-icon",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "eot" => "application/vnd.ms-fontobject",
+        "otf" => "font/otf",
+        "ttf" => "font/ttf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "css" => "text/css",
+        _ => "application/octet-stream",
+    }
+}
+
 /// Gets path to workspace root directory of currently built package, or package root directory
 /// if it is not part of workspace.
 pub fn get_workspace_dir() -> Result<PathBuf> {
@@ -39,78 +92,126 @@ pub fn get_workspace_dir() -> Result<PathBuf> {
     Ok(PathBuf::from(manifest.workspace_root))
 }
 
-// Resolves CSS module file path.
-//
-// * `source_path`: Source code file path from which CSS module is requested.
-//      In host-os-style (ie. on windows - with backward, otherwise - forward slash separators).
-//      Expected to be result of `file!()` macro.
-// * `css_module_path`: CSS module file path relative to source file.
-//      In posix-style (ie. with forward slash separators).
-pub fn resolve_module_file_path(
-    source_path: &str,
-    css_module_path: &str,
-    is_windows_host: bool,
-) -> String {
-    // normalize source path separators to posix-style, since `file!()` returns host-os-style paths.
-    // not using cfg!(windows) here because it corresponds to target (which is 'wasm' when
-    // been built for browser), not host os on which building is happening
-    let source_path_normalized = if is_windows_host {
-        source_path.replace('\\', "/")
-    } else {
-        source_path.to_owned()
-    };
-
-    join_paths(&source_path_normalized, css_module_path)
+/// Gets directory which files matching glob pattern are searched in, ie. leading path components
+/// without wildcards (eg. `src/components` for `src/components/**/*.css`).
+pub fn glob_base_dir(pattern: &str) -> PathBuf {
+    let mut base_dir = PathBuf::new();
+    let mut components = Path::new(pattern).components().peekable();
+
+    while let Some(component) = components.next() {
+        let is_last = components.peek().is_none();
+        let has_wildcards = component
+            .as_os_str()
+            .to_string_lossy()
+            .contains(['*', '?', '[']);
+
+        if is_last || has_wildcards {
+            break;
+        }
+
+        base_dir.push(component);
+    }
+
+    base_dir
 }
 
-/// Joins file paths
+/// Decodes percent-encoded characters of URL path (eg. `a%20b.png` to `a b.png`).
 ///
-/// Intention is to perform path joining with basic two-dot normalization as fast as possible.
-/// Two-dot normalization will only happen for two-dots at the beginning of rhs path.
-/// Both paths expected to be in posix-style (ie. with forward slash separators).
-fn join_paths(lhs: &str, rhs: &str) -> String {
-    let mut lhs = lhs.trim_end_matches(|c| c != '/');
-    let mut rhs = rhs;
-
-    while rhs.starts_with("../") {
-        lhs = lhs.trim_end_matches(|c| c != '/');
-        lhs = lhs.strip_suffix('/').unwrap_or("");
-        lhs = lhs.trim_end_matches(|c| c != '/');
-        rhs = rhs.strip_prefix("../").unwrap_or("");
+/// Invalid escape sequences are left as is.
+pub fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while let Some(&byte) = bytes.get(index) {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| byte == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(escaped) => {
+                decoded.push(escaped);
+                index += 3;
+            }
+            None => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
     }
 
-    lhs.to_owned() + rhs
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Normalizes file path by resolving one-dot and two-dot components lexically.
+///
+/// Does not access file system, so symbolic links are not resolved.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
 }
 
 #[cfg(test)]
-mod join_paths {
+mod normalize_path {
     use super::*;
 
     #[test]
     fn basic() {
-        assert_eq!(join_paths("a/b/c", "d"), "a/b/d");
-        assert_eq!(join_paths("a/b/", "d"), "a/b/d");
-        assert_eq!(join_paths("a/b/", "c/d"), "a/b/c/d");
+        assert_eq!(normalize_path(Path::new("/a/b/c")), Path::new("/a/b/c"));
+        assert_eq!(normalize_path(Path::new("/a/./b/./c")), Path::new("/a/b/c"));
+        assert_eq!(normalize_path(Path::new("/a/b/../c")), Path::new("/a/c"));
+        assert_eq!(normalize_path(Path::new("/a/b/../../c")), Path::new("/c"));
     }
 
     #[test]
-    fn two_dot_normalization() {
-        assert_eq!(join_paths("a/b/", "../d"), "a/d");
-        assert_eq!(join_paths("a/b/c", "../d"), "a/d");
-        assert_eq!(join_paths("a/b/c", "../../d"), "d");
-        assert_eq!(join_paths("a/b/c", "../../../d"), "d");
+    fn relative() {
+        assert_eq!(normalize_path(Path::new("./a/b")), Path::new("a/b"));
+        assert_eq!(normalize_path(Path::new("../a/../b")), Path::new("../b"));
+        assert_eq!(normalize_path(Path::new("../../a")), Path::new("../../a"));
+        assert_eq!(normalize_path(Path::new("/../a")), Path::new("/a"));
     }
+}
+
+#[cfg(test)]
+mod glob_base_dir {
+    use super::*;
 
     #[test]
-    fn exceptions() {
-        // doesn't normalize one dot
-        assert_eq!(join_paths("a/./b/c", "./d"), "a/./b/./d");
+    fn basic() {
+        assert_eq!(glob_base_dir("src/**/*.css"), Path::new("src"));
+        assert_eq!(glob_base_dir("src/a/*.css"), Path::new("src/a"));
+        assert_eq!(glob_base_dir("/src/a[bc]/*.css"), Path::new("/src"));
+        assert_eq!(glob_base_dir("src/a.css"), Path::new("src"));
+        assert_eq!(glob_base_dir("*.css"), Path::new(""));
+    }
+}
 
-        // doesn't normalize two dots not at the beginning of rhs
-        assert_eq!(join_paths("a/../b/", "c/../d"), "a/../b/c/../d");
+#[cfg(test)]
+mod percent_decode {
+    use super::*;
 
-        // doesn't work with back slash separators
-        assert_eq!(join_paths("a\\b\\c", "d"), "d");
-        assert_eq!(join_paths("a/b/c", "..\\d"), "a/b/..\\d");
+    #[test]
+    fn basic() {
+        assert_eq!(percent_decode("a.png"), "a.png");
+        assert_eq!(percent_decode("a%20b.png"), "a b.png");
+        assert_eq!(percent_decode("%D1%84.png"), "ф.png");
+        assert_eq!(percent_decode("a%2x%.png"), "a%2x%.png");
     }
 }
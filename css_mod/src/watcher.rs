@@ -0,0 +1,184 @@
+use crate::cache::Cache;
+use crate::compiler::Compiler;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Modification time and size of file, or none if file cannot be accessed.
+type FileState = Option<(SystemTime, u64)>;
+
+/// Recompiles CSS modules when they or files they depend on are changed.
+///
+/// Modules transformed by previous compilation are kept in memory, so only changed modules and
+/// modules importing or composing from them are parsed and transformed again.
+pub struct Watcher<'c> {
+    compiler: &'c Compiler,
+    css_bundle_path: &'c str,
+    mappings_path: &'c str,
+    is_compiled: bool,
+    // modules transformed by previous compilation, or none if global names depend on order of
+    // names across stylesheet
+    cache: Option<Cache>,
+    // paths of input modules of previous compilation, or none if they failed to resolve
+    input_modules: Option<BTreeSet<PathBuf>>,
+    // states of files previous compilation depends on
+    files: BTreeMap<PathBuf, FileState>,
+}
+
+impl<'c> Watcher<'c> {
+    pub fn new(compiler: &'c Compiler, css_bundle_path: &'c str, mappings_path: &'c str) -> Self {
+        Watcher {
+            compiler,
+            css_bundle_path,
+            mappings_path,
+            is_compiled: false,
+            cache: None,
+            input_modules: None,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Compiles CSS modules, unless they were already compiled and nothing has changed since.
+    ///
+    /// Returns result of compilation, or none if compilation was skipped.
+    pub fn poll(&mut self) -> Option<Result<()>> {
+        if self.is_compiled && !self.is_changed() {
+            return None;
+        }
+
+        self.is_compiled = true;
+        Some(self.compile())
+    }
+
+    /// Checks whether input modules were added or removed, or files previous compilation
+    /// depends on were changed.
+    fn is_changed(&self) -> bool {
+        let input_modules = self
+            .compiler
+            .input_modules()
+            .ok()
+            .map(|input_modules| input_modules.into_keys().collect());
+
+        input_modules != self.input_modules
+            || self
+                .files
+                .iter()
+                .any(|(path, state)| file_state(path) != *state)
+    }
+
+    fn compile(&mut self) -> Result<()> {
+        let input_modules = self.compiler.input_modules();
+        self.input_modules = input_modules
+            .as_ref()
+            .ok()
+            .map(|input_modules| input_modules.keys().cloned().collect());
+        let input_modules = input_modules?;
+
+        // take states of files before compilation, so files changed during compilation are
+        // compiled again
+        let states: BTreeMap<PathBuf, FileState> = self
+            .files
+            .keys()
+            .chain(input_modules.keys())
+            .map(|path| (path.clone(), file_state(path)))
+            .collect();
+
+        let mut stylesheet = self.compiler.stylesheet(&input_modules)?;
+        let is_cached = !stylesheet.naming.is_order_dependent();
+        stylesheet.cache = self.cache.take();
+
+        let result = stylesheet.add_modules(input_modules.keys());
+
+        if is_cached {
+            // fingerprint is not checked since compiler options are the same for all compilations
+            self.cache = Some(Cache::new(&stylesheet, 0));
+        }
+
+        // keep watching files of previous compilation if this one failed, so changes which fix
+        // the error are noticed
+        let mut paths: BTreeSet<&PathBuf> = match result {
+            Ok(_) => BTreeSet::new(),
+            Err(_) => self.files.keys().collect(),
+        };
+        paths.extend(input_modules.keys());
+        paths.extend(stylesheet.modules.keys());
+        paths.extend(
+            stylesheet
+                .modules
+                .values()
+                .flat_map(|module| module.assets.keys()),
+        );
+
+        self.files = paths
+            .into_iter()
+            .map(|path| {
+                let state = match states.get(path) {
+                    Some(state) => *state,
+                    None => file_state(path),
+                };
+                (path.clone(), state)
+            })
+            .collect();
+
+        result?;
+        self.compiler
+            .write_build_output(&stylesheet, self.css_bundle_path, self.mappings_path)
+    }
+}
+
+fn file_state(file_path: &Path) -> FileState {
+    let metadata = fs::metadata(file_path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::utils::{write_file, write_test_files};
+
+    #[test]
+    fn recompiles_changed_modules() {
+        let dir = write_test_files(
+            "recompiles_changed_modules",
+            &[("src/a.css", ".a { color: red; }")],
+        );
+        let write = |file_name: &str, content: &str| {
+            write_file(&dir.join(file_name), content.to_owned()).unwrap();
+        };
+        let read = |file_name: &str| fs::read_to_string(dir.join(file_name)).unwrap();
+
+        let mut compiler = Compiler::new();
+        compiler
+            .base_dir(dir.to_str().unwrap())
+            .unwrap()
+            .naming("[name]__[local]")
+            .unwrap()
+            .add_modules("src/*.css")
+            .unwrap();
+        let mut watcher = Watcher::new(&compiler, "out/app.css", "out/mappings.json");
+
+        assert!(watcher.poll().unwrap().is_ok());
+        assert!(watcher.poll().is_none());
+        assert_eq!(read("out/app.css"), ".a__a { color: red; }\n");
+        let mappings_modified = file_state(&dir.join("out/mappings.json"));
+
+        // mappings are left untouched when only declarations are changed
+        write("src/a.css", ".a { color: green; }");
+        assert!(watcher.poll().unwrap().is_ok());
+        assert_eq!(read("out/app.css"), ".a__a { color: green; }\n");
+        assert_eq!(
+            file_state(&dir.join("out/mappings.json")),
+            mappings_modified
+        );
+
+        // errors don't stop watching
+        write("src/b.css", ".b { color; }");
+        assert!(watcher.poll().unwrap().is_err());
+        write("src/b.css", ".b { color: blue; }");
+        assert!(watcher.poll().unwrap().is_ok());
+        assert!(read("out/mappings.json").contains("src/b.css"));
+    }
+}
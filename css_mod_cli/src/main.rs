@@ -0,0 +1,221 @@
+//! Command line tool for [css_mod](https://docs.rs/css_mod) crate, which compiles and inspects CSS
+//! modules outside of cargo build script.
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use css_mod::{Compiler, Mode, Naming};
+use std::collections::BTreeMap;
+use std::fs;
+use std::process;
+
+#[derive(Parser)]
+#[command(name = "css_mod", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compiles CSS modules into CSS bundle and JSON file with name mappings
+    Build {
+        #[command(flatten)]
+        input: Input,
+        /// Output CSS bundle file
+        #[arg(long)]
+        bundle: String,
+        /// Output JSON file with name mappings of CSS modules
+        #[arg(long)]
+        mappings: String,
+    },
+    /// Builds CSS bundle and name mappings, and rebuilds them whenever CSS modules are changed
+    ///
+    /// Name mappings file is only rewritten when names are added or removed, so Rust code using
+    /// it with `css_mod::Compiler::use_mappings` is not recompiled when only declarations change.
+    Watch {
+        #[command(flatten)]
+        input: Input,
+        /// Output CSS bundle file
+        #[arg(long)]
+        bundle: String,
+        /// Output JSON file with name mappings of CSS modules
+        #[arg(long)]
+        mappings: String,
+    },
+    /// Parses and transforms CSS modules without writing any output, to check them for errors
+    Check {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Finds CSS module and local name, which global name was generated for
+    Lookup {
+        /// Global name (eg. `todo__root__1a2b3c4d`)
+        global_name: String,
+        /// JSON file with name mappings of CSS modules written by `build` command
+        #[arg(long)]
+        mappings: String,
+    },
+}
+
+/// Input CSS modules and options of their transformation, same as of `css_mod::Compiler`.
+#[derive(Args)]
+struct Input {
+    /// Glob patterns of input CSS modules (eg. `src/**/*.css`)
+    #[arg(required = true)]
+    modules: Vec<String>,
+    /// Directory relative paths are resolved against, and which module paths in name mappings
+    /// and hashes of global names are relative to (should be workspace root directory to get the
+    /// same names as in build script)
+    #[arg(long, default_value = ".")]
+    base_dir: String,
+    /// Leave names as is, unless they are switched to local scope with `:local`
+    #[arg(long)]
+    global: bool,
+    /// Template of global names (eg. `[name]__[local]__[hash:8]`)
+    #[arg(long, conflicts_with = "minify")]
+    naming: Option<String>,
+    /// Generate shortest possible global names
+    #[arg(long)]
+    minify: bool,
+    /// Prefix of minified global names
+    #[arg(long, requires = "minify")]
+    minify_prefix: Option<String>,
+    /// Salt mixed into hashes of global names
+    #[arg(long)]
+    naming_salt: Option<String>,
+    /// Max size of asset file in bytes to inline it into CSS bundle as data URI
+    #[arg(long)]
+    inline_assets: Option<u64>,
+}
+
+impl Input {
+    fn compiler(&self) -> Result<Compiler> {
+        let mut compiler = Compiler::new();
+        compiler.base_dir(&self.base_dir)?;
+
+        if self.global {
+            compiler.default_mode(Mode::Global);
+        }
+
+        if let Some(naming) = &self.naming {
+            compiler.naming(naming.as_str())?;
+        } else if self.minify {
+            compiler.naming(match &self.minify_prefix {
+                Some(prefix) => Naming::Minified {
+                    prefix: prefix.clone(),
+                },
+                None => Naming::minified(),
+            })?;
+        }
+
+        if let Some(salt) = &self.naming_salt {
+            compiler.naming_salt(salt);
+        }
+
+        if let Some(limit) = self.inline_assets {
+            compiler.inline_assets(limit);
+        }
+
+        for pattern in &self.modules {
+            compiler.add_modules(pattern)?;
+        }
+
+        Ok(compiler)
+    }
+}
+
+type Mappings = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Finds pairs of module path and local name, which global name was generated for.
+///
+/// Global name is also found in modules importing its module, since they export names of
+/// imported modules too.
+fn lookup<'m>(mappings: &'m Mappings, global_name: &str) -> Vec<(&'m str, &'m str)> {
+    mappings
+        .iter()
+        .flat_map(|(module_path, names)| {
+            names
+                .iter()
+                // global names of composing classes are followed by composed ones
+                .filter(|(_, global)| global.split_whitespace().next() == Some(global_name))
+                .map(move |(local, _)| (module_path.as_str(), local.as_str()))
+        })
+        .collect()
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::Build {
+            input,
+            bundle,
+            mappings,
+        } => input.compiler()?.build(&bundle, &mappings),
+        Command::Watch {
+            input,
+            bundle,
+            mappings,
+        } => input
+            .compiler()?
+            .watch(&bundle, &mappings, |result| match result {
+                Ok(()) => println!("Built {} and {}", bundle, mappings),
+                Err(error) => eprintln!("Error: {:#}", error),
+            }),
+        Command::Check { input } => input.compiler()?.check(),
+        Command::Lookup {
+            global_name,
+            mappings,
+        } => {
+            let content = fs::read_to_string(&mappings)
+                .with_context(|| format!("Failed to read name mappings: {:?}", mappings))?;
+            let mappings: Mappings = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse name mappings: {:?}", mappings))?;
+
+            let found = lookup(&mappings, &global_name);
+            if found.is_empty() {
+                return Err(anyhow!("Global name was not found: {}", global_name));
+            }
+
+            for (module_path, local_name) in found {
+                println!("{}: {}", module_path, local_name);
+            }
+
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    if let Err(error) = run(Cli::parse()) {
+        eprintln!("Error: {:#}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_global_name() {
+        let mappings: Mappings = [
+            ("src/a.css", [("a", "a__1 b__2"), ("b", "b__2")]),
+            ("src/c.css", [("b", "b__2"), ("c", "c__3")]),
+        ]
+        .into_iter()
+        .map(|(path, names)| {
+            let names = names
+                .into_iter()
+                .map(|(local, global)| (local.to_owned(), global.to_owned()))
+                .collect();
+            (path.to_owned(), names)
+        })
+        .collect();
+
+        assert_eq!(lookup(&mappings, "a__1"), [("src/a.css", "a")]);
+        assert_eq!(
+            lookup(&mappings, "b__2"),
+            [("src/a.css", "b"), ("src/c.css", "b")]
+        );
+        assert!(lookup(&mappings, "d__4").is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+//! Procedural macros for [css_mod](https://docs.rs/css_mod) crate.
+//!
+//! Not intended to be used directly, since macros are re-exported from `css_mod` crate.
+
+use heck::ToSnakeCase;
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use syn::{parse_macro_input, Ident, LitStr};
+
+// should be in sync with the file name css_mod::Compiler writes exports into, since proc macro
+// crates cannot export anything but macros
+const EXPORTS_FILE_NAME: &str = "css_mod_exports.json";
+
+// should be in sync with the file name css_mod::Compiler writes urls of CSS chunks into
+const CHUNKS_FILE_NAME: &str = "css_mod_chunks.json";
+
+// should be in sync with the file name css_mod::Compiler writes paths of embedded CSS files into
+const TEXTS_FILE_NAME: &str = "css_mod_texts.json";
+
+/// Gets name mapping for CSS module.
+///
+/// Mapping is resolved at compile time and embedded into binary as static table, so there is no
+/// runtime cost of looking it up.
+///
+/// # Arguments
+///
+/// * `file_path`: string literal with relative path to CSS module in posix-style (ie. with forward
+///   slash separators).
+///
+/// # Example
+///
+/// ```ignore
+/// // my-component.rs
+///
+/// let css = css_mod::get!("my-component.css");
+/// let global_class_name = css["local-class-name"];
+/// ```
+#[proc_macro]
+pub fn get(input: TokenStream) -> TokenStream {
+    let file_path = parse_macro_input!(input as LitStr);
+
+    match expand_get(&file_path) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Gets name mapping for CSS module, or error if CSS module was not found.
+///
+/// Same as `css_mod::get!()`, but does not fail compilation if CSS module was not found.
+///
+/// # Example
+///
+/// ```ignore
+/// // my-component.rs
+///
+/// let css = css_mod::try_get!("my-component.css").unwrap();
+/// let variant = "primary";
+/// let global_class_name = css.get(variant).unwrap_or_default();
+/// ```
+#[proc_macro]
+pub fn try_get(input: TokenStream) -> TokenStream {
+    let file_path = parse_macro_input!(input as LitStr);
+
+    match expand_try_get(&file_path) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Gets name mapping for CSS module as a struct with one field per local name.
+///
+/// Fields are named after local names converted to snake case (eg. `css.local_class_name` for
+/// `.local-class-name`), so misspelled names are caught at compile time.
+///
+/// # Arguments
+///
+/// * `file_path`: string literal with relative path to CSS module in posix-style (ie. with forward
+///   slash separators).
+///
+/// # Example
+///
+/// ```ignore
+/// // my-component.rs
+///
+/// let css = css_mod::module!("my-component.css");
+/// let global_class_name = css.local_class_name;
+/// ```
+#[proc_macro]
+pub fn module(input: TokenStream) -> TokenStream {
+    let file_path = parse_macro_input!(input as LitStr);
+
+    match expand_module(&file_path) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+type Exports = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Exports, URLs of chunks and path of embedded CSS file of CSS module.
+struct Module {
+    path: String,
+    names: Option<BTreeMap<String, String>>,
+    chunk_urls: Vec<String>,
+    css_text_path: Option<String>,
+}
+
+/// Reads exports, chunks and embedded CSS of all CSS modules written by `css_mod::Compiler`, and picks ones of
+/// requested CSS module.
+fn read_module(file_path: &LitStr) -> syn::Result<Module> {
+    let error = |message: String| syn::Error::new(file_path.span(), message);
+
+    let out_dir = env::var("OUT_DIR").map_err(|_| {
+        error(
+            "OUT_DIR environment variable was not found. \
+                Help: setup css_mod::Compiler in build.rs"
+                .into(),
+        )
+    })?;
+    let out_dir = Path::new(&out_dir);
+    let exports_file_path = out_dir.join(EXPORTS_FILE_NAME);
+
+    let exports = fs::read_to_string(&exports_file_path).map_err(|_| {
+        error(format!(
+            "Failed to read CSS module exports: {:?}. \
+                Help: call css_mod::Compiler::compile() in build.rs",
+            exports_file_path
+        ))
+    })?;
+    let mut exports: Exports = serde_json::from_str(&exports)
+        .map_err(|err| error(format!("Failed to parse CSS module exports: {}", err)))?;
+
+    // chunks file is absent if it was written by older version of compiler
+    let chunk_urls: BTreeMap<String, Vec<String>> =
+        match fs::read_to_string(out_dir.join(CHUNKS_FILE_NAME)) {
+            Ok(chunk_urls) => serde_json::from_str(&chunk_urls)
+                .map_err(|err| error(format!("Failed to parse CSS chunk urls: {}", err)))?,
+            Err(_) => BTreeMap::new(),
+        };
+
+    // texts file is absent if it was written by older version of compiler
+    let mut css_text_paths: BTreeMap<String, String> =
+        match fs::read_to_string(out_dir.join(TEXTS_FILE_NAME)) {
+            Ok(css_text_paths) => serde_json::from_str(&css_text_paths)
+                .map_err(|err| error(format!("Failed to parse CSS text paths: {}", err)))?,
+            Err(_) => BTreeMap::new(),
+        };
+
+    // same path as file!() returns, ie. relative to workspace directory
+    let source_path = proc_macro::Span::call_site().file();
+    let path = resolve_module_file_path(&source_path, &file_path.value());
+
+    Ok(Module {
+        names: exports.remove(&path),
+        chunk_urls: chunk_urls.get(&path).cloned().unwrap_or_default(),
+        css_text_path: css_text_paths.remove(&path),
+        path,
+    })
+}
+
+/// Generates code loading CSS chunks of module at runtime.
+fn load_chunks(chunk_urls: &[String]) -> TokenStream2 {
+    if chunk_urls.is_empty() {
+        return TokenStream2::new();
+    }
+
+    quote! {
+        static LOADED: ::core::sync::atomic::AtomicBool =
+            ::core::sync::atomic::AtomicBool::new(false);
+        ::css_mod::load_chunks(&LOADED, &[#(#chunk_urls),*]);
+    }
+}
+
+fn module_not_found(file_path: &LitStr, module_path: &str) -> syn::Error {
+    syn::Error::new(
+        file_path.span(),
+        format!(
+            "CSS module was not found: {}. \
+                Help: add it to css_mod::Compiler in build.rs",
+            module_path
+        ),
+    )
+}
+
+fn mapping(module: &Module, names: &BTreeMap<String, String>) -> TokenStream2 {
+    let module_path = &module.path;
+
+    // names are already sorted by local names, as required for static table
+    let names = names
+        .iter()
+        .map(|(local_name, global_name)| quote! {(#local_name, #global_name)});
+    let css_text = module
+        .css_text_path
+        .as_ref()
+        .map(|css_text_path| quote! { .with_css_text(include_str!(#css_text_path)) });
+    let load_chunks = load_chunks(&module.chunk_urls);
+
+    quote! {{
+        static MAPPING: ::css_mod::Mapping<'static> =
+            ::css_mod::Mapping::new(#module_path, &[#(#names),*]) #css_text;
+        #load_chunks
+        &MAPPING
+    }}
+}
+
+fn expand_get(file_path: &LitStr) -> syn::Result<TokenStream2> {
+    let module = read_module(file_path)?;
+
+    let names = module
+        .names
+        .as_ref()
+        .ok_or_else(|| module_not_found(file_path, &module.path))?;
+
+    Ok(mapping(&module, names))
+}
+
+fn expand_try_get(file_path: &LitStr) -> syn::Result<TokenStream2> {
+    let module = read_module(file_path)?;
+    let module_path = &module.path;
+
+    Ok(match &module.names {
+        Some(names) => {
+            let mapping = mapping(&module, names);
+            quote! {
+                ::core::result::Result::<_, ::css_mod::MappingError>::Ok(#mapping)
+            }
+        }
+        None => quote! {
+            ::core::result::Result::<&'static ::css_mod::Mapping<'static>, _>::Err(
+                ::css_mod::MappingError::ModuleNotFound(::std::string::String::from(#module_path))
+            )
+        },
+    })
+}
+
+fn expand_module(file_path: &LitStr) -> syn::Result<TokenStream2> {
+    let error = |message: String| syn::Error::new(file_path.span(), message);
+    let module = read_module(file_path)?;
+    let module_path = &module.path;
+
+    let names = module
+        .names
+        .as_ref()
+        .ok_or_else(|| module_not_found(file_path, module_path))?;
+
+    let mut fields: BTreeMap<String, (&String, &String)> = BTreeMap::new();
+
+    for (local_name, global_name) in names {
+        let field = field_name(local_name);
+
+        if let Some((other_local_name, _)) = fields.insert(field.clone(), (local_name, global_name))
+        {
+            return Err(error(format!(
+                "Local names `{}` and `{}` of CSS module {} have the same field name `{}`",
+                other_local_name, local_name, module_path, field
+            )));
+        }
+    }
+
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|(field, (local_name, global_name))| {
+            let ident = field_ident(field);
+            let doc = format!("`{}`", local_name);
+            (ident, doc, global_name)
+        })
+        .collect();
+
+    let declarations = fields.iter().map(|(ident, doc, _)| {
+        quote! {
+            #[doc = #doc]
+            pub #ident: &'static str
+        }
+    });
+    let initializers = fields
+        .iter()
+        .map(|(ident, _, global_name)| quote! { #ident: #global_name });
+
+    let load_chunks = load_chunks(&module.chunk_urls);
+
+    Ok(quote! {{
+        #load_chunks
+
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        struct CssModule {
+            #(#declarations,)*
+        }
+
+        CssModule {
+            #(#initializers,)*
+        }
+    }})
+}
+
+/// Resolves CSS module file path relative to workspace directory in posix-style.
+///
+/// Relies on `cfg!(windows)`, which corresponds to the host since proc macros are running on it.
+fn resolve_module_file_path(source_path: &str, css_module_path: &str) -> String {
+    let source_path = if cfg!(windows) {
+        source_path.replace('\\', "/")
+    } else {
+        source_path.to_owned()
+    };
+
+    let mut lhs = source_path.trim_end_matches(|c| c != '/');
+    let mut rhs = css_module_path;
+
+    while let Some(rest) = rhs.strip_prefix("../") {
+        lhs = lhs.trim_end_matches(|c| c != '/');
+        lhs = lhs.strip_suffix('/').unwrap_or("");
+        lhs = lhs.trim_end_matches(|c| c != '/');
+        rhs = rest;
+    }
+
+    lhs.to_owned() + rhs.strip_prefix("./").unwrap_or(rhs)
+}
+
+/// Converts local name to snake case field name (eg. `local-class-name` -> `local_class_name`).
+fn field_name(local_name: &str) -> String {
+    let field = local_name.to_snake_case();
+
+    if field.is_empty() || field.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", field)
+    } else {
+        field
+    }
+}
+
+fn field_ident(field: &str) -> Ident {
+    if syn::parse_str::<Ident>(field).is_ok() {
+        format_ident!("{}", field)
+    } else if syn::parse_str::<Ident>(&format!("r#{}", field)).is_ok() {
+        // keywords (eg. `.type`)
+        Ident::new_raw(field, Span::call_site())
+    } else {
+        // keywords which cannot be raw identifiers (eg. `.self`)
+        format_ident!("{}_", field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_field_names() {
+        assert_eq!(field_name("root"), "root");
+        assert_eq!(field_name("local-class-name"), "local_class_name");
+        assert_eq!(field_name("localClassName"), "local_class_name");
+        assert_eq!(field_name("button--primary"), "button_primary");
+        assert_eq!(field_name("1st"), "_1st");
+
+        assert_eq!(field_ident("type").to_string(), "r#type");
+        assert_eq!(field_ident("self").to_string(), "self_");
+    }
+
+    #[test]
+    fn resolves_module_file_path() {
+        let resolve = resolve_module_file_path;
+
+        assert_eq!(resolve("src/main.rs", "styles.css"), "src/styles.css");
+        assert_eq!(resolve("src/main.rs", "./styles.css"), "src/styles.css");
+        assert_eq!(resolve("src/a/b.rs", "../styles.css"), "src/styles.css");
+        assert_eq!(resolve("a/b/c", "../../d"), "d");
+        assert_eq!(resolve("a/b/c", "../../../d"), "d");
+    }
+}
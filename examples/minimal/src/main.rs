@@ -1,6 +1,7 @@
 fn main() {
-    css_mod::init!();
-
     let css = css_mod::get!("styles.css");
     dbg!(css["local-class-name"]);
+
+    let css = css_mod::module!("styles.css");
+    dbg!(css.local_class_name);
 }
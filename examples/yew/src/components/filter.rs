@@ -18,7 +18,7 @@ pub fn Filter(props: &Props) -> Html {
     };
 
     html! {
-        <li class={classes!(css["root"], props.selected.then(|| css["selected"]))}>
+        <li class={css.cx([("root", true), ("selected", props.selected)]).to_string()}>
             <a href={props.filter.as_href()} onclick={on_set_filter}>
                 { props.filter }
             </a>
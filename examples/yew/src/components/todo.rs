@@ -25,10 +25,10 @@ pub fn Todo(props: &Props) -> Html {
         move |_| on_remove.emit(id)
     };
 
-    let mut class = classes!(css["root"], props.class.clone());
-    if props.todo.completed {
-        class.push(css["completed"]);
-    }
+    let class = css
+        .cx([("root", true), ("completed", props.todo.completed)])
+        .extra([props.class.to_string()])
+        .to_string();
 
     html! {
         <li {class}>
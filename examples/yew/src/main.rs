@@ -4,6 +4,5 @@ mod state;
 use components::App;
 
 fn main() {
-    css_mod::init!();
     yew::Renderer::<App>::new().render();
 }